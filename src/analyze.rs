@@ -0,0 +1,546 @@
+//Native analysis of an MXF file
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::time::{Duration, Instant};
+use crate::klv::{self, KlvReader};
+use crate::metadata::{self, HeaderMetadata, Package, PackageKind, Position, Track};
+use crate::partition::{PartitionKind, PartitionPack};
+use crate::primer::{LocalSet, Primer};
+use crate::rip::{self, RandomIndexPack};
+use crate::output::Output;
+use crate::timecode;
+
+// Everything we learnt about one file
+#[derive(Debug, Default)]
+pub struct Analysis {
+    // Origin properties found on the tracks, with the package and track carrying them
+    pub origins: Vec<OriginFinding>,
+    // Partition packs in file order
+    pub partitions: Vec<PartitionPack>,
+    // Header metadata copies, one per partition carrying metadata
+    pub metadata: Vec<HeaderMetadata>,
+    // Index in `metadata` of the copy the origins were taken from
+    pub source: Option<usize>,
+    // Random Index Pack found at the end of the file
+    pub rip: Option<RandomIndexPack>,
+    // Problems found while resolving the header metadata
+    pub errors: Vec<String>,
+}
+
+// One Origin property, located in the header metadata graph
+#[derive(Debug, Clone)]
+pub struct OriginFinding {
+    pub partition: PartitionKind,
+    pub package: Package,
+    pub track: Track,
+    // Origin in edit units of the track
+    pub origin: Position,
+}
+
+// What an Origin value means for playout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OriginKind {
+    // Playback starts at the first edit unit, nothing to report
+    Zero,
+    // Positive origin: the first edit units are precharge to be skipped
+    Precharge,
+    // Negative origin: playback starts before the first stored edit unit
+    Negative,
+}
+
+impl OriginKind {
+    pub fn of(origin: Position) -> OriginKind {
+        match origin {
+            0 => OriginKind::Zero,
+            o if o > 0 => OriginKind::Precharge,
+            _ => OriginKind::Negative,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OriginKind::Zero => "zero",
+            OriginKind::Precharge => "precharge",
+            OriginKind::Negative => "negative",
+        }
+    }
+}
+
+// Which package types an Origin must sit in to be flagged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OriginPolicy {
+    // Material package precharge is the one NLEs trip on
+    Material,
+    Source,
+    Both,
+}
+
+impl OriginPolicy {
+    pub fn from_name(name: &str) -> Option<OriginPolicy> {
+        match name {
+            "material" => Some(OriginPolicy::Material),
+            "source" => Some(OriginPolicy::Source),
+            "both" => Some(OriginPolicy::Both),
+            _ => None,
+        }
+    }
+
+    pub fn applies_to(&self, kind: PackageKind) -> bool {
+        match self {
+            OriginPolicy::Material => kind == PackageKind::Material,
+            OriginPolicy::Source => kind == PackageKind::Source,
+            OriginPolicy::Both => true,
+        }
+    }
+}
+
+impl OriginFinding {
+    pub fn kind(&self) -> OriginKind {
+        OriginKind::of(self.origin)
+    }
+
+    // Origin as edit units, seconds, timecode and samples at the track edit rate
+    pub fn time(&self) -> String {
+        match &self.track.edit_rate {
+            Some(rate) => timecode::describe(self.origin, rate, self.package.frame_rate().as_ref()),
+            None => format!("{} edit units (no EditRate)", self.origin),
+        }
+    }
+
+    // A non-zero Origin in a package type the policy looks at
+    pub fn is_flagged(&self, policy: OriginPolicy) -> bool {
+        self.kind() != OriginKind::Zero && policy.applies_to(self.package.kind)
+    }
+
+    // Describe where the Origin sits, e.g. Material Package "clip" / track 1 (MXFTrack)
+    pub fn location(&self) -> String {
+        let package = match &self.package.name {
+            Some(name) => format!("{} \"{}\"", self.package.kind.name(), name),
+            None => self.package.kind.name().to_string(),
+        };
+        let track = match self.track.track_id {
+            Some(id) => format!("track {}", id),
+            None => "track ?".to_string(),
+        };
+        let track_name = match &self.track.name {
+            Some(name) => format!(" \"{}\"", name),
+            None => String::new(),
+        };
+        let value = match self.track.origin_offset {
+            Some(offset) => format!(", Origin value at {:#x}", offset),
+            None => String::new(),
+        };
+        format!(
+            "{} partition, {} / {}{} ({} at {:#x}{})",
+            self.partition.name(),
+            package,
+            track,
+            track_name,
+            self.track.set_name,
+            self.track.offset,
+            value
+        )
+    }
+}
+
+impl Analysis {
+    // Same rule as the prototypes: an Origin only counts when it isn't zero,
+    // and only in the package types the policy looks at
+    pub fn has_origin(&self, policy: OriginPolicy) -> bool {
+        self.origins.iter().any(|o| o.is_flagged(policy))
+    }
+
+    pub fn header_partition(&self) -> Option<&PartitionPack> {
+        self.partitions.iter().find(|p| p.kind == PartitionKind::Header)
+    }
+
+    pub fn footer_partition(&self) -> Option<&PartitionPack> {
+        self.partitions.iter().find(|p| p.kind == PartitionKind::Footer)
+    }
+
+    // Header metadata copy the origins were taken from
+    pub fn source_metadata(&self) -> Option<&HeaderMetadata> {
+        self.source.map(|index| &self.metadata[index])
+    }
+}
+
+// Walk the KLV packets of the file natively and collect the partition packs
+// and the Origin values found in the header metadata sets.
+// With `header_only` only the header metadata is read, plus the footer one
+// when the header partition is open or incomplete. Past `timeout` the walk
+// stops with a TimedOut error.
+pub fn analyze_file(
+    videofilepath: &str,
+    verbose: bool,
+    mxferror: bool,
+    header_only: bool,
+    timeout: Option<Duration>,
+    out: &mut Output,
+) -> io::Result<Analysis> {
+    let file = File::open(videofilepath)?;
+    let mut reader = KlvReader::new(BufReader::new(file));
+    let mut walker = Walker {
+        path: videofilepath,
+        verbose,
+        mxferror,
+        started: Instant::now(),
+        timeout,
+        analysis: Analysis::default(),
+        primer: Primer::default(),
+    };
+
+    out.line(format!("Processing {}", videofilepath));
+    if !header_only {
+        walker.walk(&mut reader, false, out)?;
+    } else if let Some(metadata_end) = walker.walk(&mut reader, true, out)? {
+        let header = walker.analysis.header_partition().cloned();
+        match header {
+            Some(header) if header.closed && header.complete => {}
+            Some(header) if header.footer_partition > header.this_partition => {
+                // The header metadata may be a placeholder, the footer one is final
+                out.line(format!("Header partition is {}, reading the footer partition at {:#x}", header.status(), header.footer_partition));
+                reader.seek(header.footer_partition);
+                walker.walk(&mut reader, true, out)?;
+            }
+            Some(header) => {
+                // An open header doesn't know where the footer is, the Random Index Pack does.
+                // A damaged pack locates nothing, the whole file is read as without one.
+                walker.analysis.rip = match rip::read_rip(reader.get_mut()) {
+                    Ok(rip) => rip,
+                    Err(e) => {
+                        if mxferror {
+                            out.error(format!("Random Index Pack error in {} : {}", videofilepath, e));
+                        }
+                        None
+                    }
+                };
+                match walker.analysis.rip.as_ref().and_then(RandomIndexPack::footer_offset) {
+                    Some(footer) if footer > header.this_partition => {
+                        out.line(format!("Header partition is {}, reading the footer partition at {:#x} found by the Random Index Pack", header.status(), footer));
+                        reader.seek(footer);
+                        walker.walk(&mut reader, true, out)?;
+                    }
+                    _ => {
+                        out.line(format!("Header partition is {} and doesn't locate the footer, reading the whole file", header.status()));
+                        reader.seek(metadata_end);
+                        walker.walk(&mut reader, false, out)?;
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    let mut analysis = walker.analysis;
+    resolve_origins(&mut analysis, verbose, out);
+    Ok(analysis)
+}
+
+// State kept while walking the packets of one file
+struct Walker<'a> {
+    path: &'a str,
+    verbose: bool,
+    mxferror: bool,
+    started: Instant,
+    timeout: Option<Duration>,
+    analysis: Analysis,
+    // Each metadata partition carries its own primer, sets use the latest one
+    primer: Primer,
+}
+
+impl Walker<'_> {
+    // Read packets until the end of the file, or with `one_partition` until the end
+    // of the header metadata of the first partition read. Returns where that
+    // header metadata ends, when a partition pack was read.
+    fn walk<R: Read + Seek>(&mut self, reader: &mut KlvReader<R>, one_partition: bool, out: &mut Output) -> io::Result<Option<u64>> {
+        let videofilepath = self.path;
+        let mut metadata_end = None;
+        while let Some(result) = reader.next() {
+            if let Some(timeout) = self.timeout
+                && self.started.elapsed() >= timeout
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("native analysis stopped after {:.1} s", self.started.elapsed().as_secs_f64()),
+                ));
+            }
+            let klv = match result {
+                Ok(klv) => klv,
+                Err(e) => {
+                    // A damaged packet ends the walk, the rest of the file can't be located
+                    if self.mxferror {
+                        out.error(format!("KLV error in {} : {}", videofilepath, e));
+                    }
+                    break;
+                }
+            };
+            let is_partition = crate::partition::partition_kind(&klv.key).is_some();
+            // HeaderByteCount covers the primer, the sets and their fill, essence or the next partition follow
+            if one_partition && metadata_end.is_some_and(|end| is_partition || klv.offset >= end) {
+                break;
+            }
+            if self.verbose {
+                out.line(format!("[ K = {} ( {:016x} )", klv::key_name(&klv.key), klv.offset));
+                out.line(format!("{}, L = {:>10} ({:x}), LL = {} ]", klv::format_ul(&klv.key), klv.length, klv.length, klv.length_size()));
+            }
+
+            match klv::key_name(&klv.key) {
+                "Primer" => {
+                    let value = reader.read_value(&klv)?;
+                    match Primer::parse(&value) {
+                        Ok(p) => self.primer = p,
+                        Err(e) => {
+                            if self.mxferror {
+                                out.error(format!("Primer pack error in {} at {:#x} : {}", videofilepath, klv.offset, e));
+                            }
+                        }
+                    }
+                }
+                "LocalSet" => {
+                    let value = reader.read_value(&klv)?;
+                    let set = match LocalSet::parse(&klv, &value, &self.primer) {
+                        Ok(set) => set,
+                        Err(e) => {
+                            if self.mxferror {
+                                out.error(format!("Local set error in {} at {:#x} : {}", videofilepath, klv.offset, e));
+                            }
+                            continue;
+                        }
+                    };
+                    if self.verbose {
+                        for item in &set.items {
+                            let ul = item.ul.as_ref().map_or("unknown".to_string(), klv::format_ul);
+                            out.line(format!("  [ k = {}", ul));
+                            out.line(format!("  {:02x}.{:02x}, l = {:>5} ({:04x}) ]", item.tag >> 8, item.tag & 0xff, item.value.len(), item.value.len()));
+                        }
+                    }
+                    match self.analysis.metadata.last_mut() {
+                        Some(metadata) => metadata.add(set),
+                        None => {
+                            if self.mxferror {
+                                out.error(format!("Local set outside of any partition in {} at {:#x}", videofilepath, klv.offset));
+                            }
+                        }
+                    }
+                }
+                "RandomIndexMetadata" => {
+                    let value = reader.read_value(&klv)?;
+                    match RandomIndexPack::parse(klv.offset, &value) {
+                        Ok(rip) => self.analysis.rip = Some(rip),
+                        Err(e) => {
+                            if self.mxferror {
+                                out.error(format!("Random Index Pack error in {} at {:#x} : {}", videofilepath, klv.offset, e));
+                            }
+                        }
+                    }
+                }
+                _ if is_partition => {
+                    let value = reader.read_value(&klv)?;
+                    match PartitionPack::parse(&klv.key, &value) {
+                        Ok(partition) => {
+                            // HeaderByteCount comes from the file, without a usable one the walk goes on to the end
+                            metadata_end = klv.value_offset.checked_add(klv.length).and_then(|end| end.checked_add(partition.header_byte_count));
+                            if metadata_end.is_none() && self.mxferror {
+                                out.error(format!(
+                                    "Partition pack error in {} at {:#x} : HeaderByteCount {:#x} runs past the end of the file",
+                                    videofilepath, klv.offset, partition.header_byte_count
+                                ));
+                            }
+                            // Sets following this pack belong to its header metadata
+                            if partition.header_byte_count > 0 {
+                                self.analysis.metadata.push(HeaderMetadata::new(&partition, klv.offset));
+                            }
+                            self.analysis.partitions.push(partition);
+                        }
+                        Err(e) => {
+                            if self.mxferror {
+                                out.error(format!("Partition pack error in {} at {:#x} : {}", videofilepath, klv.offset, e));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(metadata_end)
+    }
+}
+
+// Walk each header metadata graph, and keep the tracks carrying an Origin property
+// in the most authoritative copy: an open or incomplete header may only hold
+// placeholder values that the footer metadata corrects
+pub fn resolve_origins(analysis: &mut Analysis, verbose: bool, out: &mut Output) {
+    let mut best: Option<(usize, Vec<Package>)> = None;
+    for (index, metadata) in analysis.metadata.iter().enumerate().filter(|(_, m)| !m.is_empty()) {
+        let packages = match metadata.packages() {
+            Ok(packages) => packages,
+            Err(e) => {
+                analysis.errors.push(format!("{} partition at {:#x}: {}", metadata.partition.name(), metadata.partition_offset, e));
+                continue;
+            }
+        };
+        if verbose {
+            print_packages(metadata, &packages, out);
+        }
+        if best.as_ref().is_none_or(|(best, _)| metadata.authority() > analysis.metadata[*best].authority()) {
+            best = Some((index, packages));
+        }
+    }
+
+    let Some((index, packages)) = best else { return };
+    let metadata = &analysis.metadata[index];
+    for package in &packages {
+        for track in &package.tracks {
+            // Origin is found by its UL, whatever local tag the primer mapped it to
+            if let Some(origin) = track.origin {
+                analysis.origins.push(OriginFinding {
+                    partition: metadata.partition,
+                    package: package.clone(),
+                    track: track.clone(),
+                    origin,
+                });
+            }
+        }
+    }
+    analysis.source = Some(index);
+}
+
+// Print the resolved graph, one line per package, track and sequence
+fn print_packages(metadata: &HeaderMetadata, packages: &[Package], out: &mut Output) {
+    out.line(format!("\n--- {} partition metadata ( {:016x} ) ---", metadata.partition.name(), metadata.partition_offset));
+    for package in packages {
+        out.line(format!(
+            "{} ( {:016x} ) {} {}",
+            package.set_name,
+            package.offset,
+            package.uid.as_deref().map_or("-".to_string(), metadata::format_uid),
+            package.name.as_deref().unwrap_or("")
+        ));
+        for track in &package.tracks {
+            out.line(format!(
+                "  {} ( {:016x} ) TrackID = {} TrackNumber = {:08x} EditRate = {} {}",
+                track.set_name,
+                track.offset,
+                track.track_id.map_or("-".to_string(), |id| id.to_string()),
+                track.track_number.unwrap_or(0),
+                track.edit_rate.map_or("-".to_string(), |rate| rate.to_string()),
+                track.name.as_deref().unwrap_or("")
+            ));
+            if let Some(sequence) = &track.sequence {
+                out.line(format!("    MXFSequence ( {:016x} ) [ {} ]", sequence.offset, sequence.components.join(", ")));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::testmxf::{self, MxfFile};
+
+    fn analyze(path: &str, header_only: bool) -> Analysis {
+        analyze_file(path, false, true, header_only, None, &mut Output::default()).unwrap()
+    }
+
+    // Header, body and footer partitions, with header metadata in the header and the footer
+    fn file(closed: bool, complete: bool, header_origin: Position) -> MxfFile {
+        let mut file = MxfFile::default().partition(testmxf::HEADER, closed, complete).metadata(header_origin, 0);
+        // Past HeaderByteCount, a set read as header metadata would replace the material track
+        let (key, value) = testmxf::sets(99, 0).swap_remove(3);
+        file.bytes.extend(testmxf::klv(&key, &value));
+        file.essence(256)
+            .partition(testmxf::BODY, true, true)
+            .essence(256)
+            .partition(testmxf::FOOTER, true, true)
+            .metadata(16, 0)
+    }
+
+    fn kinds(analysis: &Analysis) -> Vec<PartitionKind> {
+        analysis.partitions.iter().map(|partition| partition.kind).collect()
+    }
+
+    #[test]
+    fn closed_complete_header_is_read_alone() {
+        let path = file(true, true, 16).link_footer().write("closed-header.mxf");
+        let analysis = analyze(&path, true);
+        assert_eq!(kinds(&analysis), [PartitionKind::Header]);
+        assert_eq!(analysis.source_metadata().unwrap().partition, PartitionKind::Header);
+        let origins: Vec<Position> = analysis.origins.iter().map(|finding| finding.origin).collect();
+        assert_eq!(origins, [16, 0]);
+        // The full walk sees every partition
+        assert_eq!(kinds(&analyze(&path, false)), [PartitionKind::Header, PartitionKind::Body, PartitionKind::Footer]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn open_header_reads_the_footer_it_locates() {
+        let path = file(false, false, 0).link_footer().write("open-header.mxf");
+        let analysis = analyze(&path, true);
+        assert_eq!(kinds(&analysis), [PartitionKind::Header, PartitionKind::Footer]);
+        assert_eq!(analysis.source_metadata().unwrap().partition, PartitionKind::Footer);
+        assert!(analysis.has_origin(OriginPolicy::Material));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn open_header_without_footer_offset_is_read_whole() {
+        let path = file(false, false, 0).write("unlinked-header.mxf");
+        let analysis = analyze(&path, true);
+        assert_eq!(kinds(&analysis), [PartitionKind::Header, PartitionKind::Body, PartitionKind::Footer]);
+        assert_eq!(analysis.source_metadata().unwrap().partition, PartitionKind::Footer);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn footer_copy_wins_over_an_open_header() {
+        let path = file(false, true, 0).write("open-complete-header.mxf");
+        let analysis = analyze(&path, false);
+        assert_eq!(analysis.metadata.len(), 2);
+        assert_eq!(analysis.source_metadata().unwrap().partition, PartitionKind::Footer);
+        assert!(analysis.origins.iter().all(|finding| finding.partition == PartitionKind::Footer));
+        assert!(analysis.has_origin(OriginPolicy::Material));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn open_header_finds_the_footer_through_the_rip() {
+        let path = file(false, false, 0).rip().write("rip.mxf");
+        let analysis = analyze(&path, true);
+        assert_eq!(kinds(&analysis), [PartitionKind::Header, PartitionKind::Footer]);
+        assert_eq!(analysis.rip.as_ref().and_then(RandomIndexPack::footer_offset), analysis.footer_partition().map(|p| p.this_partition));
+        assert_eq!(analysis.source_metadata().unwrap().partition, PartitionKind::Footer);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn damaged_rip_falls_back_on_the_whole_file() {
+        let mut file = file(false, false, 0);
+        // 5 bytes of entries, not a whole one, then the overall length
+        let value = [vec![0; 5], 29u32.to_be_bytes().to_vec()].concat();
+        file.bytes.extend(testmxf::klv(&rip::RIP_KEY, &value));
+        let path = file.write("damaged-rip.mxf");
+        let analysis = analyze(&path, true);
+        assert_eq!(kinds(&analysis), [PartitionKind::Header, PartitionKind::Body, PartitionKind::Footer]);
+        assert_eq!(analysis.source_metadata().unwrap().partition, PartitionKind::Footer);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn header_byte_count_past_the_end_of_the_file_is_walked_unbounded() {
+        let file = MxfFile::default()
+            .partition(testmxf::HEADER, true, true)
+            .metadata(16, 0)
+            .set(0, testmxf::HEADER_BYTE_COUNT, u64::MAX)
+            .essence(256)
+            .partition(testmxf::FOOTER, true, true)
+            .metadata(16, 0);
+        let path = file.write("overflow.mxf");
+        for header_only in [false, true] {
+            let analysis = analyze(&path, header_only);
+            assert_eq!(analysis.partitions.len(), 2);
+            assert_eq!(analysis.origins.len(), 2);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
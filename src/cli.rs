@@ -0,0 +1,205 @@
+//Command line definition
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use crate::analyze::OriginPolicy;
+
+pub fn build_cli() -> App<'static, 'static> {
+    let verbose = Arg::with_name("verbose")
+        .short("v")
+        .long("verbose")
+        .help("Print every step, KLV packet and metadata set");
+    let origin_packages = Arg::with_name("origin-packages")
+        .short("p")
+        .long("origin-packages")
+        .takes_value(true)
+        .possible_values(&["material", "source", "both"])
+        .default_value("both")
+        .help("Package types whose non-zero Origin is flagged");
+
+    App::new("whereismyorigin")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Finds MXF files whose tracks carry a non-zero Origin (precharge)")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .after_help(
+            "EXIT CODES:\n    0     every file is clean\n    1     at least one file has a flagged Origin\n    \
+             2     at least one file couldn't be analysed, or isn't analysed yet\n    64    usage error",
+        )
+        .subcommand(
+            SubCommand::with_name("scan")
+                .about("Adds the MXF files of folders to the database and marks modified ones for reanalysis")
+                .arg(
+                    Arg::with_name("dirs")
+                        .help("Folders to scan recursively for .mxf files")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("hash")
+                        .long("hash")
+                        .help("Also fingerprint the file content, slower but catches copies keeping size and mtime"),
+                )
+                .arg(
+                    Arg::with_name("prune")
+                        .long("prune")
+                        .help("Remove the entries of missing files under the scanned folders instead of keeping tombstones"),
+                )
+                .arg(verbose.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .about("Reads the Origin of every new or modified file of the database")
+                .arg(
+                    Arg::with_name("force")
+                        .short("f")
+                        .long("force")
+                        .help("Reanalyse every file, even the unchanged ones"),
+                )
+                .arg(
+                    Arg::with_name("errors")
+                        .short("e")
+                        .long("errors")
+                        .help("Print the KLV and metadata parsing errors"),
+                )
+                .arg(origin_packages.clone())
+                .arg(
+                    Arg::with_name("jobs")
+                        .short("j")
+                        .long("jobs")
+                        .takes_value(true)
+                        .default_value("1")
+                        .validator(|jobs| match jobs.parse::<usize>() {
+                            Ok(jobs) if jobs > 0 => Ok(()),
+                            _ => Err(format!("'{}' is not a number of jobs greater than 0", jobs)),
+                        })
+                        .help("Number of files analysed at once"),
+                )
+                .arg(
+                    Arg::with_name("dumper")
+                        .long("dumper")
+                        .takes_value(true)
+                        .env("WHEREISMYORIGIN_DUMPER")
+                        .possible_values(&["native", "mxfdump", "mxf2raw", "command", "stub"])
+                        .default_value("native")
+                        .help("Backend reading the files: the native parser, AAF MXFDump, bmx mxf2raw --info, a command printing MXFDump text, or a saved dump whose results aren't saved"),
+                )
+                .arg(
+                    Arg::with_name("dumper-path")
+                        .long("dumper-path")
+                        .takes_value(true)
+                        .env("WHEREISMYORIGIN_DUMPER_PATH")
+                        .required_if("dumper", "stub")
+                        .help("Binary of the mxfdump or mxf2raw backend (default ./bin/MXFDump.exe and mxf2raw), or dump text served by the stub"),
+                )
+                .arg(
+                    Arg::with_name("dumper-command")
+                        .long("dumper-command")
+                        .takes_value(true)
+                        .env("WHEREISMYORIGIN_DUMPER_COMMAND")
+                        .required_if("dumper", "command")
+                        .help("Command of the command backend, {file} is replaced by the file path, e.g. \"wine MXFDump.exe {file}\""),
+                )
+                .arg(
+                    Arg::with_name("timeout")
+                        .short("t")
+                        .long("timeout")
+                        .takes_value(true)
+                        .env("WHEREISMYORIGIN_TIMEOUT")
+                        .validator(|timeout| match timeout.parse::<u64>() {
+                            Ok(timeout) if timeout > 0 => Ok(()),
+                            _ => Err(format!("'{}' is not a number of seconds greater than 0", timeout)),
+                        })
+                        .help("Seconds a dumper may spend on one file before it is stopped, or killed when external, and the file marked Timeout"),
+                )
+                .arg(
+                    Arg::with_name("header-only")
+                        .long("header-only")
+                        .help("Only read the header metadata, and the footer one when the header partition is open or incomplete"),
+                )
+                .arg(verbose.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("analyze-dump")
+                .about("Reads the Origin from saved MXFDump text, without the MXF file or the database")
+                .arg(
+                    Arg::with_name("files")
+                        .help("MXFDump text files to analyse")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(origin_packages.clone())
+                .arg(verbose.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("report")
+                .about("Prints the analysis results stored in the database")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "json", "ndjson", "csv"])
+                        .default_value("text")
+                        .help("Output format, json, ndjson and csv list every file with all its Origins"),
+                )
+                .arg(verbose.clone().help("Also list zero Origins and files without Origin")),
+        )
+        .subcommand(
+            SubCommand::with_name("query")
+                .about("Looks up files in the database by path or by status")
+                .arg(
+                    Arg::with_name("paths")
+                        .help("Paths of the files to look up")
+                        .multiple(true)
+                        .required_unless("status"),
+                )
+                .arg(
+                    Arg::with_name("status")
+                        .short("s")
+                        .long("status")
+                        .takes_value(true)
+                        .possible_values(&["Unscanned", "NoOrigin", "HasOrigin", "Error", "Deleted", "Timeout"])
+                        .help("List the files having this status"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fix")
+                .about("Rewrites the recorded non-zero Origin values of files to zero, in place")
+                .arg(
+                    Arg::with_name("paths")
+                        .help("Paths of the files to fix, every HasOrigin file of the database when none is given")
+                        .multiple(true),
+                )
+                .arg(origin_packages.clone().help("Package types whose non-zero Origin is zeroed"))
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Copy each file to <file>.bak before writing, the old values always go to <file>.origin-journal"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .short("n")
+                        .long("dry-run")
+                        .help("Only print the values that would be zeroed"),
+                )
+                .arg(verbose.clone()),
+        )
+}
+
+// Which package types to flag or fix: material, source or both
+pub fn origin_policy(args: &ArgMatches) -> OriginPolicy {
+    args.value_of("origin-packages")
+        .and_then(OriginPolicy::from_name)
+        .unwrap_or(OriginPolicy::Both)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stub_without_a_dump_is_a_usage_error() {
+        let error = build_cli().get_matches_from_safe(["whereismyorigin", "analyze", "--dumper", "stub"]).unwrap_err();
+        assert_eq!(error.kind, clap::ErrorKind::MissingRequiredArgument);
+        let args = ["whereismyorigin", "analyze", "--dumper", "stub", "--dumper-path", "dump.txt"];
+        assert!(build_cli().get_matches_from_safe(args).is_ok());
+    }
+}
@@ -0,0 +1,337 @@
+//Origin detection on the text printed by MXFDump
+use std::io::{self, Read};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use crate::analyze::{self, Analysis};
+use crate::dumptext::{parse_tag, parse_ul, DumpParser, Event, LineSplitter};
+use crate::klv::{self, UL};
+use crate::metadata::HeaderMetadata;
+use crate::output::Output;
+use crate::partition::{self, PartitionPack};
+use crate::primer::{LocalItem, LocalSet, Primer};
+use crate::rip::{RandomIndexPack, RipEntry};
+
+// Rebuilds the partition packs, primers and header metadata sets from the events
+// of MXFDump text, so that the native Origin resolution can run on them
+#[derive(Default)]
+pub struct DumpReader {
+    parser: DumpParser,
+    analysis: Analysis,
+    // Primer of the current partition, sets use the latest one
+    primer: Primer,
+    // Top level packet being read
+    packet: Option<Packet>,
+}
+
+// One `[ K = Name ( offset )` packet and what was printed inside it
+struct Packet {
+    name: String,
+    offset: u64,
+    key: UL,
+    // Length of the value, the local properties of a set should fill it
+    length: u64,
+    // Absolute file offset of the next property, MXFDump only prints the offset of packets
+    next_item: u64,
+    // "Name = value" lines of partition packs
+    fields: Vec<(String, String)>,
+    // Properties of local sets, filled by the hex rows following them
+    items: Vec<LocalItem>,
+    // Length announced for the last property
+    expected: usize,
+    // Tags of a primer, partitions of a Random Index Pack
+    primer: Primer,
+    rip: Vec<RipEntry>,
+}
+
+impl DumpReader {
+    // Feed one line of the dump, without its line break
+    pub fn line(&mut self, line: &str) {
+        match self.parser.line(line) {
+            Some(Event::Packet { name, offset, key, length, length_size }) => {
+                self.finish_packet();
+                // The offsets come from the text, a packet past the largest file offset is left out
+                let Some(next_item) = offset.checked_add(16 + length_size as u64) else {
+                    self.analysis.errors.push(format!("{} at {:#x}: offset past the end of any file", name, offset));
+                    return;
+                };
+                self.packet = Some(Packet {
+                    name,
+                    offset,
+                    key,
+                    length,
+                    next_item,
+                    fields: Vec::new(),
+                    items: Vec::new(),
+                    expected: 0,
+                    primer: Primer::default(),
+                    rip: Vec::new(),
+                });
+            }
+            Some(Event::Property { tag, length, .. }) => {
+                let Some(packet) = self.packet.as_mut() else { return };
+                // Each property is a 2-byte tag and a 2-byte length followed by its value
+                match packet.next_item.checked_add(4 + length as u64) {
+                    Some(next_item) => {
+                        let value_offset = next_item - length as u64;
+                        packet.items.push(LocalItem { tag, ul: self.primer.ul(tag).copied(), value: Vec::with_capacity(length), value_offset });
+                        packet.next_item = next_item;
+                        packet.expected = length;
+                    }
+                    None => {
+                        self.analysis.errors.push(format!("{} at {:#x}: properties past the end of any file", packet.name, packet.offset));
+                        self.packet = None;
+                    }
+                }
+            }
+            Some(Event::Row(bytes)) => {
+                if let Some(item) = self.packet.as_mut().and_then(|packet| packet.items.last_mut()) {
+                    item.value.extend(bytes);
+                }
+            }
+            Some(Event::Text(text)) => {
+                if let Some(packet) = self.packet.as_mut() {
+                    packet.text(text.trim());
+                }
+            }
+            None => {}
+        }
+    }
+
+    // End of the dump, resolve the Origins of the metadata read
+    pub fn finish(mut self, verbose: bool, out: &mut Output) -> Analysis {
+        self.finish_packet();
+        analyze::resolve_origins(&mut self.analysis, verbose, out);
+        self.analysis
+    }
+
+    fn finish_packet(&mut self) {
+        let Some(packet) = self.packet.take() else { return };
+        let key = packet.key;
+        if packet.items.last().is_some_and(|item| item.value.len() < packet.expected) {
+            self.analysis.errors.push(format!("{} at {:#x}: truncated property value", packet.name, packet.offset));
+        } else if !packet.items.is_empty() && packet.items.iter().map(|item| 4 + item.value.len() as u64).sum::<u64>() != packet.length {
+            self.analysis.errors.push(format!("{} at {:#x}: properties don't fill the set length", packet.name, packet.offset));
+        }
+
+        if let Some(partition) = partition_pack(&key, &packet.fields) {
+            // Sets following this pack belong to its header metadata
+            if partition.header_byte_count > 0 {
+                self.analysis.metadata.push(HeaderMetadata::new(&partition, packet.offset));
+            }
+            self.analysis.partitions.push(partition);
+            return;
+        }
+        match klv::key_name(&key) {
+            "Primer" => self.primer = packet.primer,
+            "RandomIndexMetadata" => {
+                self.analysis.rip = Some(RandomIndexPack { offset: packet.offset, entries: packet.rip });
+            }
+            "LocalSet" => {
+                let set = LocalSet { key, offset: packet.offset, items: packet.items };
+                match self.analysis.metadata.last_mut() {
+                    Some(metadata) => metadata.add(set),
+                    None => self.analysis.errors.push(format!("{} at {:#x}: local set outside of any partition", packet.name, packet.offset)),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Analyse a whole saved dump, as `analyze-dump` does without the MXF file
+pub fn read_dump<R: Read>(reader: R, verbose: bool, out: &mut Output) -> io::Result<Analysis> {
+    let mut dump = DumpReader::default();
+    for_each_line(reader, |line| dump.line(line))?;
+    Ok(dump.finish(verbose, out))
+}
+
+// Call `f` on every line without its line break. UTF-16 text, as written by
+// Windows redirection, is transcoded on the fly, invalid UTF-8 is replaced
+pub fn for_each_line<R: Read>(mut reader: R, mut f: impl FnMut(&str)) -> io::Result<()> {
+    // Enough text to tell the encoding, whatever the size of the reads
+    let mut start = Vec::new();
+    reader.by_ref().take(SNIFF_SIZE).read_to_end(&mut start)?;
+    let encoding = sniff_encoding(&start);
+    // A BOM, when there is one, wins over the sniffed encoding and is stripped
+    let mut reader = DecodeReaderBytesBuilder::new().encoding(encoding).build(io::Cursor::new(start).chain(reader));
+
+    let mut lines = LineSplitter::default();
+    let mut chunk = vec![0u8; 8192];
+    loop {
+        match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => lines.push(&chunk[..read], &mut f)?,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    lines.finish(f);
+    Ok(())
+}
+
+// Bytes looked at to tell UTF-16 from UTF-8
+const SNIFF_SIZE: u64 = 512;
+
+// UTF-16 without BOM is told apart by its null bytes: ASCII text has one in every
+// pair, the high byte, second in little endian and first in big endian
+fn sniff_encoding(start: &[u8]) -> Option<&'static Encoding> {
+    let pairs = start.len() / 2;
+    let nulls = |high: usize| start.chunks_exact(2).filter(|pair| pair[high] == 0 && pair[1 - high] != 0).count();
+    if pairs == 0 {
+        None
+    } else if nulls(1) * 2 > pairs {
+        Some(UTF_16LE)
+    } else if nulls(0) * 2 > pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+impl Packet {
+    // Lines that aren't properties, read according to the packet
+    fn text(&mut self, trimmed: &str) {
+        match self.name.as_str() {
+            // 3c.0a     :    06.0e.2b.34.01.01.01.01.01.01.15.02.00.00.00.00
+            "Primer" => {
+                if let Some((tag, ul)) = trimmed.split_once(':')
+                    && let (Some(tag), Some(ul)) = (parse_tag(tag.trim()), parse_ul(ul.trim()))
+                {
+                    self.primer.insert(tag, ul);
+                }
+            }
+            // 1 :             2    0000000000007200
+            "RandomIndexMetadata" => {
+                let fields: Vec<&str> = trimmed.split_whitespace().collect();
+                if let [_, ":", body_sid, offset] = fields[..]
+                    && let (Ok(body_sid), Ok(offset)) = (body_sid.parse(), u64::from_str_radix(offset, 16))
+                {
+                    self.rip.push(RipEntry { body_sid, offset });
+                }
+            }
+            // ThisPartition = 0000000000000000
+            _ => {
+                if let Some((name, value)) = trimmed.split_once(" = ") {
+                    self.fields.push((name.trim().to_string(), value.trim().to_string()));
+                }
+            }
+        }
+    }
+}
+
+// Partition pack from the fields MXFDump prints for it
+fn partition_pack(key: &UL, fields: &[(String, String)]) -> Option<PartitionPack> {
+    let (kind, closed, complete) = partition::partition_kind(key)?;
+    let field = |name: &str| fields.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str());
+    let hex = |name: &str| field(name).and_then(|value| u64::from_str_radix(value, 16).ok()).unwrap_or(0);
+    Some(PartitionPack {
+        kind,
+        closed,
+        complete,
+        major_version: hex("Major Version") as u16,
+        minor_version: hex("Minor Version") as u16,
+        kag_size: hex("KAGSize") as u32,
+        this_partition: hex("ThisPartition"),
+        previous_partition: hex("PreviousPartition"),
+        footer_partition: hex("FooterPartition"),
+        header_byte_count: hex("HeaderByteCount"),
+        index_byte_count: hex("IndexByteCount"),
+        index_sid: hex("IndexSID") as u32,
+        body_offset: hex("BodyOffset"),
+        body_sid: hex("BodySID") as u32,
+        operational_pattern: field("Operational Pattern").and_then(parse_ul).unwrap_or_default(),
+        // Essence containers are printed as "0 = 06.0e.2b.34..."
+        essence_containers: fields
+            .iter()
+            .filter(|(name, _)| name.parse::<u32>().is_ok())
+            .filter_map(|(_, value)| parse_ul(value))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Serves at most `size` bytes per read, as a pipe may
+    struct Chunked<'a> {
+        data: &'a [u8],
+        size: usize,
+    }
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.size.min(buf.len()).min(self.data.len());
+            buf[..read].copy_from_slice(&self.data[..read]);
+            self.data = &self.data[read..];
+            Ok(read)
+        }
+    }
+
+    fn text(data: &[u8], size: usize) -> String {
+        let mut text = String::new();
+        for_each_line(Chunked { data, size }, |line| {
+            text.push_str(line);
+            text.push('\n');
+        })
+        .unwrap();
+        text
+    }
+
+    // Every fixture is read at every chunk size from 1 byte to a whole read buffer,
+    // so that each line break and each UTF-16 code unit falls on a chunk boundary
+    const FIXTURES: [(&str, usize); 3] = [
+        ("assets/WithOrigin/testa0Mxfdump.txt", 4),
+        ("assets/NoOrigin/testa0Mxfdump.txt", 0),
+        // UTF-16LE with a BOM
+        ("assets/Original Dump/testa0Mxfdump.txt", 0),
+    ];
+
+    #[test]
+    fn every_chunk_size_reads_the_same_lines() {
+        for (fixture, _) in FIXTURES {
+            let data = fs::read(fixture).unwrap();
+            let whole = text(&data, data.len());
+            assert!(whole.contains("[ k = Origin\n  4b.02, l =     8 (0008) ]\n"));
+            for size in 1..=8192 {
+                assert!(text(&data, size) == whole, "{} read {} bytes at a time", fixture, size);
+            }
+        }
+    }
+
+    #[test]
+    fn offsets_past_the_end_of_any_file_are_errors() {
+        let track = |offset: u64| {
+            format!(
+                "\
+[ K = MXFTrack ( {:016x} )
+06.0e.2b.34.02.53.01.01.0d.01.01.01.01.01.3b.00, L =         12 (c), LL = 4 ]
+  [ k = Origin
+  4b.02, l =     8 (0008) ]
+       0  00 00 00 00 00 00 00 10                            ........
+",
+                offset
+            )
+        };
+        // The packet itself, then its Origin property
+        for offset in [0xfffffffffffffff8, u64::MAX - 30] {
+            let analysis = read_dump(track(offset).as_bytes(), false, &mut Output::default()).unwrap();
+            assert_eq!(analysis.errors.len(), 1, "{:#x}", offset);
+            assert!(analysis.origins.is_empty());
+        }
+    }
+
+    #[test]
+    fn fixtures_have_the_expected_origins() {
+        // The parser only sees lines, and the test above checks that the lines are the
+        // same at every chunk size, so one read per fixture covers them all
+        for (fixture, non_zero) in FIXTURES {
+            let data = fs::read(fixture).unwrap();
+            let analysis = read_dump(Chunked { data: &data, size: 8192 }, false, &mut Output::default()).unwrap();
+            assert_eq!(analysis.origins.len(), 4, "{}", fixture);
+            assert_eq!(analysis.origins.iter().filter(|o| o.origin == 0x10).count(), non_zero, "{}", fixture);
+            assert!(analysis.origins.iter().all(|o| o.origin == 0 || o.origin == 0x10), "{}", fixture);
+        }
+    }
+}
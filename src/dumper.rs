@@ -0,0 +1,472 @@
+//Backends analysing one file: the native KLV parser or an external dump tool
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::analyze::{self, Analysis, OriginFinding};
+use crate::dump;
+use crate::metadata::{Package, PackageKind, Track};
+use crate::output::Output;
+use crate::partition::PartitionKind;
+use crate::timecode::Rational;
+
+// Analysis settings shared by every backend
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    pub verbose: bool,
+    // Also print the errors of the parser or of the dump tool
+    pub mxferror: bool,
+    // Only read the header (and footer) metadata, native backend only
+    pub header_only: bool,
+    // Kill an external dumper running longer than this on one file
+    pub timeout: Option<Duration>,
+}
+
+pub trait Dumper: Sync {
+    // Recorded with each result, e.g. "native 0.1.0" or "mxfdump ./bin/MXFDump.exe {file}"
+    fn name(&self) -> String;
+
+    // Fresh invocation for one file: the program and arguments to run, or for the
+    // in-process backends their name and the file path
+    fn argv(&self, videofilepath: &str) -> Vec<String>;
+
+    // Analyse the file of an invocation built by argv()
+    fn analyze(&self, argv: &[String], options: &Options, out: &mut Output) -> io::Result<Analysis>;
+}
+
+// Walks the KLV packets of the file itself, no external binary needed
+pub struct Native;
+
+impl Dumper for Native {
+    fn name(&self) -> String {
+        format!("native {}", env!("CARGO_PKG_VERSION"))
+    }
+
+    fn argv(&self, videofilepath: &str) -> Vec<String> {
+        vec!["native".to_string(), videofilepath.to_string()]
+    }
+
+    fn analyze(&self, argv: &[String], options: &Options, out: &mut Output) -> io::Result<Analysis> {
+        analyze::analyze_file(&argv[1], options.verbose, options.mxferror, options.header_only, options.timeout, out)
+    }
+}
+
+// Text printed by a dump tool on its standard output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextFormat {
+    // AAF SDK MXFDump, every KLV packet and local set property in hex
+    MxfDump,
+    // bmx `mxf2raw --info`, a summary of the clip and its tracks
+    Mxf2RawInfo,
+}
+
+// Runs an external tool on each file and analyses what it prints
+pub struct External {
+    kind: &'static str,
+    // Program then arguments, "{file}" is replaced by the file path
+    template: Vec<String>,
+    format: TextFormat,
+}
+
+impl External {
+    // AAF SDK MXFDump, shipped as bin/MXFDump.exe
+    pub fn mxfdump(path: &str) -> External {
+        External { kind: "mxfdump", template: vec![path.to_string(), "{file}".to_string()], format: TextFormat::MxfDump }
+    }
+
+    // bmx mxf2raw, only reports the precharge it derives from the track Origins
+    pub fn mxf2raw(path: &str) -> External {
+        External {
+            kind: "mxf2raw",
+            template: vec![path.to_string(), "--info".to_string(), "{file}".to_string()],
+            format: TextFormat::Mxf2RawInfo,
+        }
+    }
+
+    // Any command printing MXFDump text, e.g. "wine bin/MXFDump.exe {file}".
+    // The path is appended when the template has no {file}.
+    pub fn command(template: &str) -> Result<External, String> {
+        let mut template: Vec<String> = template.split_whitespace().map(str::to_string).collect();
+        if template.is_empty() {
+            return Err("the dumper command is empty".to_string());
+        }
+        if !template.iter().any(|arg| arg.contains("{file}")) {
+            template.push("{file}".to_string());
+        }
+        Ok(External { kind: "command", template, format: TextFormat::MxfDump })
+    }
+}
+
+impl Dumper for External {
+    fn name(&self) -> String {
+        format!("{} {}", self.kind, self.template.join(" "))
+    }
+
+    // Built from the template for each file, nothing is carried over between files
+    fn argv(&self, videofilepath: &str) -> Vec<String> {
+        self.template.iter().map(|arg| arg.replace("{file}", videofilepath)).collect()
+    }
+
+    fn analyze(&self, argv: &[String], options: &Options, out: &mut Output) -> io::Result<Analysis> {
+        if options.verbose {
+            out.line(format!("Running {}", argv.join(" ")));
+        }
+        let started = Instant::now();
+        let mut command = Command::new(&argv[0]);
+        command.args(&argv[1..]).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        // Wrappers such as wine or a script run the real dumper as a grandchild,
+        // its own process group lets the timeout kill all of them at once
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut child = command
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("couldn't run {} : {}", argv[0], e)))?;
+
+        // Drain stderr on the side so that a chatty tool can't block on a full pipe
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let errors = thread::spawn(move || {
+            let mut errors = Vec::new();
+            let _ = stderr.read_to_end(&mut errors);
+            errors
+        });
+        // Parse stdout on the side too, so that this thread can watch the clock
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (format, verbose) = (self.format, options.verbose);
+        let reader = thread::spawn(move || {
+            let mut out = Output::default();
+            let analysis = read_text(BufReader::new(stdout), format, verbose, &mut out);
+            (analysis, out)
+        });
+
+        let Some(status) = wait_until(&mut child, options.timeout.map(|timeout| started + timeout))? else {
+            // The readers are left behind, they end once the killed group closes the pipes
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{} killed after {:.1} s", argv[0], started.elapsed().as_secs_f64()),
+            ));
+        };
+        let (analysis, reader_out) = reader.join().map_err(|_| io::Error::other("the dump reader panicked"))?;
+        out.append(reader_out);
+        let mut analysis = analysis?;
+        let errors = errors.join().unwrap_or_default();
+
+        if options.mxferror {
+            for line in String::from_utf8_lossy(&errors).lines().filter(|l| !l.trim().is_empty()) {
+                out.error(format!("{} : {}", argv[0], line));
+            }
+        }
+        if !status.success() {
+            // MXFDump exits with an error on damaged files after printing what it could
+            if analysis.partitions.is_empty() && analysis.origins.is_empty() {
+                return Err(io::Error::other(format!("{} exited with {}", argv[0], status)));
+            }
+            analysis.errors.push(format!("{} exited with {}", argv[0], status));
+        }
+        Ok(analysis)
+    }
+}
+
+// Wait for the process to exit, or kill it at the deadline and return None
+fn wait_until(child: &mut Child, deadline: Option<Instant>) -> io::Result<Option<ExitStatus>> {
+    let Some(deadline) = deadline else { return child.wait().map(Some) };
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            // The process may exit on its own meanwhile, then there is nothing to kill
+            kill_group(child);
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(50)));
+    }
+}
+
+// Kill the child and everything it started, see the process group set at spawn
+#[cfg(unix)]
+fn kill_group(child: &mut Child) {
+    unsafe extern "C" {
+        fn kill(pid: i32, signal: i32) -> i32;
+    }
+    const SIGKILL: i32 = 9;
+    // A negative pid targets the process group, whose id is the child's pid
+    if unsafe { kill(-(child.id() as i32), SIGKILL) } != 0 {
+        let _ = child.kill();
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(child: &mut Child) {
+    let _ = child.kill();
+}
+
+// Analyses canned MXFDump text instead of running anything, for tests and dry runs
+pub struct Stub {
+    // Raw bytes, the encoding is detected when they are read
+    output: Vec<u8>,
+}
+
+impl Stub {
+    pub fn new(output: Vec<u8>) -> Stub {
+        Stub { output }
+    }
+
+    // Serve the content of a saved dump for every file
+    pub fn from_file(path: &str) -> io::Result<Stub> {
+        Ok(Stub::new(fs::read(path)?))
+    }
+}
+
+impl Dumper for Stub {
+    fn name(&self) -> String {
+        "stub".to_string()
+    }
+
+    fn argv(&self, videofilepath: &str) -> Vec<String> {
+        vec!["stub".to_string(), videofilepath.to_string()]
+    }
+
+    fn analyze(&self, _argv: &[String], options: &Options, out: &mut Output) -> io::Result<Analysis> {
+        read_text(self.output.as_slice(), TextFormat::MxfDump, options.verbose, out)
+    }
+}
+
+// Pick the backend from its name, as given by --dumper or the configuration
+pub fn select(name: &str, path: Option<&str>, command: Option<&str>) -> io::Result<Box<dyn Dumper>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    match name {
+        "native" => Ok(Box::new(Native)),
+        "mxfdump" => Ok(Box::new(External::mxfdump(path.unwrap_or("./bin/MXFDump.exe")))),
+        "mxf2raw" => Ok(Box::new(External::mxf2raw(path.unwrap_or("mxf2raw")))),
+        "command" => External::command(command.unwrap_or_default())
+            .map(|dumper| Box::new(dumper) as Box<dyn Dumper>)
+            .map_err(invalid),
+        "stub" => match path {
+            Some(path) => Ok(Box::new(Stub::from_file(path)?)),
+            None => Err(invalid("the stub dumper needs --dumper-path to a saved dump".to_string())),
+        },
+        _ => Err(invalid(format!("unknown dumper {}", name))),
+    }
+}
+
+// Analyse the text of a dump tool line by line, as it is printed
+fn read_text<R: Read>(reader: R, format: TextFormat, verbose: bool, out: &mut Output) -> io::Result<Analysis> {
+    match format {
+        TextFormat::MxfDump => dump::read_dump(reader, verbose, out),
+        TextFormat::Mxf2RawInfo => {
+            let mut info = InfoReader::default();
+            dump::for_each_line(reader, |line| info.line(line))?;
+            Ok(info.finish())
+        }
+    }
+}
+
+// Picks the per track precharge out of `mxf2raw --info`. bmx derives it from the
+// file package track Origins and prints no partition details, so every value
+// is reported on a file package track of the header metadata.
+#[derive(Default)]
+struct InfoReader {
+    analysis: Analysis,
+    track_id: Option<u32>,
+    edit_rate: Option<Rational>,
+}
+
+impl InfoReader {
+    fn line(&mut self, line: &str) {
+        let Some((name, value)) = line.split_once(':') else { return };
+        let name = name.trim().to_lowercase();
+        let value = value.trim();
+        if let Some(number) = name.strip_prefix("track") {
+            // A new track section, e.g. "Track #1:"
+            if value.is_empty() {
+                self.track_id = number.trim().trim_start_matches('#').parse::<u32>().ok();
+                self.edit_rate = None;
+            }
+            return;
+        }
+        match name.as_str() {
+            "edit rate" => {
+                if let Some((numerator, denominator)) = value.split_whitespace().next().and_then(|rate| rate.split_once('/'))
+                    && let (Ok(numerator), Ok(denominator)) = (numerator.parse(), denominator.parse())
+                {
+                    self.edit_rate = Some(Rational { numerator, denominator });
+                }
+            }
+            "origin" | "precharge" => {
+                if let Some(Ok(origin)) = value.split_whitespace().next().map(str::parse::<i64>) {
+                    let track = Track {
+                        set_name: "MXFTrack",
+                        offset: 0,
+                        track_id: self.track_id,
+                        track_number: None,
+                        name: None,
+                        edit_rate: self.edit_rate,
+                        origin: Some(origin),
+                        // bmx prints values, not where they are stored
+                        origin_offset: None,
+                        sequence: None,
+                    };
+                    let package = Package {
+                        kind: PackageKind::Source,
+                        set_name: "MXFSourcePackage",
+                        offset: 0,
+                        uid: None,
+                        name: None,
+                        tracks: vec![track.clone()],
+                    };
+                    self.analysis.origins.push(OriginFinding { partition: PartitionKind::Header, package, track, origin });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(self) -> Analysis {
+        self.analysis
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::OriginPolicy;
+    use crate::testmxf::{self, MxfFile};
+
+    fn analyze_fixture(fixture: &str) -> Analysis {
+        let dumper = Stub::from_file(fixture).unwrap();
+        let mut out = Output::default();
+        dumper.analyze(&dumper.argv("unused.mxf"), &Options::default(), &mut out).unwrap()
+    }
+
+    #[test]
+    fn stub_finds_origin_in_saved_dump() {
+        let analysis = analyze_fixture("assets/WithOrigin/testa0Mxfdump.txt");
+        assert!(analysis.has_origin(OriginPolicy::Material));
+        assert_eq!(analysis.origins.len(), 4);
+        assert!(analysis.origins.iter().all(|o| o.origin == 0x10));
+        // Set offset, key and length, then the tag, length and value of the properties before
+        let offsets: Vec<Option<u64>> = analysis.origins.iter().map(|o| o.track.origin_offset).collect();
+        assert_eq!(offsets, [Some(0x571ca3), Some(0x571e00), Some(0x572028), Some(0x572185)]);
+    }
+
+    #[test]
+    fn stub_finds_zero_origin_in_saved_dump() {
+        let analysis = analyze_fixture("assets/NoOrigin/testa0Mxfdump.txt");
+        assert!(!analysis.has_origin(OriginPolicy::Both));
+        assert_eq!(analysis.origins.len(), 4);
+    }
+
+    fn analyze_bytes(output: Vec<u8>) -> Analysis {
+        let dumper = Stub::new(output);
+        let mut out = Output::default();
+        dumper.analyze(&dumper.argv("unused.mxf"), &Options::default(), &mut out).unwrap()
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() })
+            .collect()
+    }
+
+    #[test]
+    fn utf16_dumps_are_transcoded() {
+        let text = fs::read_to_string("assets/WithOrigin/testa0Mxfdump.txt").unwrap();
+        let with_bom = |bom: &[u8], body: Vec<u8>| [bom.to_vec(), body].concat();
+        for output in [
+            with_bom(&[0xff, 0xfe], utf16(&text, false)),
+            with_bom(&[0xfe, 0xff], utf16(&text, true)),
+            utf16(&text, false),
+            utf16(&text, true),
+        ] {
+            let analysis = analyze_bytes(output);
+            assert_eq!(analysis.origins.len(), 4);
+            assert!(analysis.origins.iter().all(|o| o.origin == 0x10));
+        }
+    }
+
+    #[test]
+    fn windows_redirected_dump_is_read() {
+        // UTF-16LE with a BOM, as written by cmd.exe redirection
+        let analysis = analyze_fixture("dump.log");
+        assert_eq!(analysis.origins.len(), 20);
+        assert!(analysis.header_partition().is_some());
+    }
+
+    #[test]
+    fn native_analysis_stops_at_the_timeout() {
+        let file = MxfFile::default().partition(testmxf::HEADER, true, true).metadata(16, 0).essence(256);
+        let path = file.write("native-timeout.mxf");
+        let options = Options { timeout: Some(Duration::ZERO), ..Options::default() };
+        let result = Native.analyze(&Native.argv(&path), &options, &mut Output::default());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        let options = Options { timeout: Some(Duration::from_secs(60)), ..Options::default() };
+        assert!(Native.analyze(&Native.argv(&path), &options, &mut Output::default()).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn mxfdump_argv_holds_only_the_given_file() {
+        let dumper = External::mxfdump("./bin/MXFDump.exe");
+        assert_eq!(dumper.argv("/media/first.mxf"), ["./bin/MXFDump.exe", "/media/first.mxf"]);
+        assert_eq!(dumper.argv("/media/second.mxf"), ["./bin/MXFDump.exe", "/media/second.mxf"]);
+    }
+
+    #[test]
+    fn command_argv_is_rebuilt_from_the_template_for_each_file() {
+        let dumper = External::command("wine MXFDump.exe").unwrap();
+        assert_eq!(dumper.argv("/media/first.mxf"), ["wine", "MXFDump.exe", "/media/first.mxf"]);
+        assert_eq!(dumper.argv("/media/second.mxf"), ["wine", "MXFDump.exe", "/media/second.mxf"]);
+
+        let dumper = External::command("mxfdump --file={file} -v").unwrap();
+        assert_eq!(dumper.argv("/media/second.mxf"), ["mxfdump", "--file=/media/second.mxf", "-v"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hung_dumper_is_killed_at_the_timeout() {
+        // The "file" is the shell script, a dumper that never prints anything
+        let dumper = External::command("sh -c {file}").unwrap();
+        let options = Options { timeout: Some(Duration::from_millis(200)), ..Options::default() };
+        let started = Instant::now();
+        let result = dumper.analyze(&dumper.argv("sleep 10"), &options, &mut Output::default());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn processes_started_by_a_hung_dumper_are_killed_too() {
+        let dir = std::env::temp_dir().join(format!("whereismyorigin-hang-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("hang.sh");
+        let pid_path = dir.join("pid");
+        fs::write(&script, format!("sleep 10 &\necho $! > {}\nwait\n", pid_path.display())).unwrap();
+        let dumper = External::command("sh {file}").unwrap();
+        let options = Options { timeout: Some(Duration::from_millis(500)), ..Options::default() };
+        let result = dumper.analyze(&dumper.argv(&script.to_string_lossy()), &options, &mut Output::default());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+        // The orphaned sleep is gone, or a zombie waiting for its new parent
+        let pid = fs::read_to_string(&pid_path).unwrap();
+        let alive = || fs::read_to_string(format!("/proc/{}/stat", pid.trim())).is_ok_and(|stat| !stat.contains(") Z "));
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while alive() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!alive());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mxf2raw_precharge_is_read_per_track() {
+        let info = "Tracks:\n  Track #0:\n    Edit rate  : 25/1\n    Precharge  : 2\n  Track #1:\n    Edit rate  : 48000/1\n    Precharge  : 0\n";
+        let mut out = Output::default();
+        let analysis = read_text(info.as_bytes(), TextFormat::Mxf2RawInfo, false, &mut out).unwrap();
+        let origins: Vec<_> = analysis.origins.iter().map(|o| (o.track.track_id, o.origin)).collect();
+        assert_eq!(origins, vec![(Some(0), 2), (Some(1), 0)]);
+        assert_eq!(analysis.origins[1].track.edit_rate.map(|r| r.numerator), Some(48000));
+    }
+}
@@ -0,0 +1,251 @@
+//Streaming parser of the text printed by MXFDump, one typed event per line
+use std::io;
+use crate::klv::UL;
+
+// Longest line kept, MXFDump prints less than 100 bytes per line
+pub const MAX_LINE: usize = 64 * 1024;
+
+// Cuts text read in chunks of any size into lines. The parser carries its state
+// from line to line, so the only bytes kept between chunks are those of the line
+// not ended yet: the window covers the longest record by construction, instead
+// of a guessed number of characters. Lines are only cut at b'\n', which never
+// occurs inside a UTF-8 sequence, so a character split by a chunk is decoded whole.
+#[derive(Default)]
+pub struct LineSplitter {
+    pending: Vec<u8>,
+}
+
+impl LineSplitter {
+    // Call `f` on every line ended in `chunk`, without its line break
+    pub fn push(&mut self, mut chunk: &[u8], mut f: impl FnMut(&str)) -> io::Result<()> {
+        while let Some(end) = chunk.iter().position(|&byte| byte == b'\n') {
+            if self.pending.is_empty() {
+                emit(&chunk[..end], &mut f);
+            } else {
+                self.pending.extend_from_slice(&chunk[..end]);
+                emit(&self.pending, &mut f);
+                self.pending.clear();
+            }
+            chunk = &chunk[end + 1..];
+        }
+        self.pending.extend_from_slice(chunk);
+        if self.pending.len() > MAX_LINE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("line longer than {} bytes, not MXFDump text", MAX_LINE)));
+        }
+        Ok(())
+    }
+
+    // End of the text, the last line may have no line break
+    pub fn finish(self, mut f: impl FnMut(&str)) {
+        if !self.pending.is_empty() {
+            emit(&self.pending, &mut f);
+        }
+    }
+}
+
+// Invalid UTF-8 is replaced
+fn emit(line: &[u8], f: &mut impl FnMut(&str)) {
+    f(String::from_utf8_lossy(line).trim_end_matches('\r'));
+}
+
+// What a line of the dump holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<'a> {
+    // Top level `[ K = Name ( offset )` packet, sent once its key line is read
+    Packet { name: String, offset: u64, key: UL, length: u64, length_size: u8 },
+    // `[ k = Name` local property, sent once its tag and length line is read
+    Property { name: String, tag: u16, length: usize },
+    // Bytes of the last property, at most 16 per row
+    Row(Vec<u8>),
+    // Any other line of a packet: partition fields, primer and RIP rows
+    Text(&'a str),
+}
+
+#[derive(Default)]
+enum State {
+    // Before the first packet, or after one whose header couldn't be read
+    #[default]
+    Outside,
+    // Waiting for the key line of a packet
+    Key { name: String, offset: u64 },
+    // Waiting for the tag line of a property
+    Tag { name: String },
+    // Inside a packet, with the bytes still expected for its last property
+    Value { remaining: usize },
+}
+
+#[derive(Default)]
+pub struct DumpParser {
+    state: State,
+}
+
+impl DumpParser {
+    // Feed one line of the dump, without its line break
+    pub fn line<'a>(&mut self, line: &'a str) -> Option<Event<'a>> {
+        let trimmed = line.trim();
+        if let Some(rest) = line.strip_prefix("[ K = ") {
+            // Name ( 0000000000000000 )
+            self.state = match rest.trim_end().trim_end_matches(')').rsplit_once(" ( ") {
+                Some((name, offset)) => match u64::from_str_radix(offset.trim(), 16) {
+                    Ok(offset) => State::Key { name: name.to_string(), offset },
+                    Err(_) => State::Outside,
+                },
+                None => State::Outside,
+            };
+            return None;
+        }
+
+        match std::mem::take(&mut self.state) {
+            State::Outside => None,
+            // 06.0e.2b.34..., L =        104 (68), LL = 4 ]
+            State::Key { name, offset } => {
+                let (key, length, length_size) = parse_key_line(trimmed)?;
+                self.state = State::Value { remaining: 0 };
+                Some(Event::Packet { name, offset, key, length, length_size })
+            }
+            // 3c.0a, l =    16 (0010) ]
+            State::Tag { name } => {
+                // The packet goes on without this property when the line can't be read
+                self.state = State::Value { remaining: 0 };
+                let (tag, length) = parse_tag_line(trimmed)?;
+                let length = usize::from(length);
+                self.state = State::Value { remaining: length };
+                Some(Event::Property { name, tag, length })
+            }
+            State::Value { remaining } => {
+                // [ k = Origin, the tag and length follow on the next line
+                if let Some(name) = trimmed.strip_prefix("[ k = ") {
+                    self.state = State::Tag { name: name.to_string() };
+                    return None;
+                }
+                if remaining == 0 {
+                    self.state = State::Value { remaining };
+                    return Some(Event::Text(line));
+                }
+                // Offset then up to 16 bytes, the ASCII column may look like hex too
+                let bytes: Vec<u8> = trimmed
+                    .split_whitespace()
+                    .skip(1)
+                    .take(remaining.min(16))
+                    .map_while(|byte| u8::from_str_radix(byte, 16).ok())
+                    .collect();
+                self.state = State::Value { remaining: remaining - bytes.len() };
+                (!bytes.is_empty()).then_some(Event::Row(bytes))
+            }
+        }
+    }
+}
+
+// Key, length and length of the BER length, from a packet key line
+fn parse_key_line(line: &str) -> Option<(UL, u64, u8)> {
+    let mut parts = line.trim_end_matches(']').split(',');
+    let key = parse_ul(parts.next()?)?;
+    let length = parts.next()?.trim().strip_prefix("L =")?.split_whitespace().next()?.parse().ok()?;
+    let length_size = parts.next()?.trim().strip_prefix("LL =")?.trim().parse().ok()?;
+    Some((key, length, length_size))
+}
+
+// Tag and length of a property, local set lengths are 2 bytes
+fn parse_tag_line(line: &str) -> Option<(u16, u16)> {
+    let (tag, length) = line.split_once(", l =")?;
+    Some((parse_tag(tag)?, length.split_whitespace().next()?.parse().ok()?))
+}
+
+// 06.0e.2b.34.01.01.01.02.07.02.01.03.01.03.00.00
+pub fn parse_ul(text: &str) -> Option<UL> {
+    let mut ul = [0u8; 16];
+    let mut parts = text.trim().split('.');
+    for byte in ul.iter_mut() {
+        *byte = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+    parts.next().is_none().then_some(ul)
+}
+
+// 4b.02
+pub fn parse_tag(text: &str) -> Option<u16> {
+    let (high, low) = text.trim().split_once('.')?;
+    Some(u16::from_str_radix(high, 16).ok()? << 8 | u16::from_str_radix(low, 16).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(text: &str) -> Vec<Event<'_>> {
+        let mut parser = DumpParser::default();
+        text.lines().filter_map(|line| parser.line(line)).collect()
+    }
+
+    #[test]
+    fn lines_split_by_chunks_are_joined() {
+        // "é" is split between two chunks, "\r\n" too
+        let text = "Processing E:\\Source\\caf\u{e9}.mxf\r\n[ K = Header ( 0000000000000000 )\nno line break";
+        let mut lines = Vec::new();
+        let mut splitter = LineSplitter::default();
+        for chunk in text.as_bytes().chunks(3) {
+            splitter.push(chunk, |line| lines.push(line.to_string())).unwrap();
+        }
+        splitter.finish(|line| lines.push(line.to_string()));
+        assert_eq!(lines, ["Processing E:\\Source\\caf\u{e9}.mxf", "[ K = Header ( 0000000000000000 )", "no line break"]);
+    }
+
+    #[test]
+    fn overlong_line_is_an_error() {
+        let mut splitter = LineSplitter::default();
+        let chunk = [b'0'; 1024];
+        let result = (0..=MAX_LINE / chunk.len()).try_for_each(|_| splitter.push(&chunk, |_| {}));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn track_origin_is_read_as_events() {
+        let text = "\
+Processing E:\\Source\\test.mxf
+[ K = MXFTrack ( 0000000000000704 )
+06.0e.2b.34.02.53.01.01.0d.01.01.01.01.01.3b.00, L =         40 (28), LL = 4 ]
+  [ k = TrackID
+  48.01, l =     4 (0004) ]
+       0  00 00 00 02                                        ....
+  [ k = Origin
+  4b.02, l =     8 (0008) ]
+       0  00 00 00 00 00 00 00 10                            ........
+";
+        let key = [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0d, 0x01, 0x01, 0x01, 0x01, 0x01, 0x3b, 0x00];
+        assert_eq!(
+            events(text),
+            vec![
+                Event::Packet { name: "MXFTrack".to_string(), offset: 0x704, key, length: 40, length_size: 4 },
+                Event::Property { name: "TrackID".to_string(), tag: 0x4801, length: 4 },
+                Event::Row(vec![0, 0, 0, 2]),
+                Event::Property { name: "Origin".to_string(), tag: 0x4b02, length: 8 },
+                Event::Row(vec![0, 0, 0, 0, 0, 0, 0, 0x10]),
+            ]
+        );
+    }
+
+    #[test]
+    fn ascii_column_is_not_read_as_bytes() {
+        // "ab" and "cd" in the ASCII column are valid hex
+        let text = "\
+[ K = MXFIdentification ( 000000000000051e )
+06.0e.2b.34.02.53.01.01.0d.01.01.01.01.01.30.00, L =         6 (6), LL = 4 ]
+  [ k = ProductName
+  3c.02, l =     2 (0002) ]
+       0  61 62                                              ab
+       0  63 64                                              cd
+";
+        assert_eq!(events(text)[2..], [Event::Row(vec![0x61, 0x62]), Event::Text("       0  63 64                                              cd")]);
+    }
+
+    #[test]
+    fn property_length_past_two_bytes_is_skipped() {
+        let text = "\
+[ K = MXFTrack ( 0000000000000704 )
+06.0e.2b.34.02.53.01.01.0d.01.01.01.01.01.3b.00, L =         40 (28), LL = 4 ]
+  [ k = Origin
+  4b.02, l = 99999999999999 (5af3107a3fff) ]
+       0  00 00 00 00 00 00 00 10                            ........
+";
+        assert_eq!(events(text)[1..], [Event::Text("       0  00 00 00 00 00 00 00 10                            ........")]);
+    }
+}
@@ -0,0 +1,37 @@
+//Process exit codes, so that QC scripts can gate on the result without parsing text
+use std::process::ExitCode;
+use crate::record::Status;
+
+// Bad arguments or a subcommand that can't run, as sysexits.h EX_USAGE
+pub const USAGE: u8 = 64;
+
+// Result of a run, ordered from best to worst so that max() keeps the worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    // Every file is clean (exit code 0)
+    Clean,
+    // At least one file has a flagged Origin (exit code 1)
+    Origin,
+    // At least one file couldn't be analysed or isn't analysed yet, or the run itself failed (exit code 2)
+    Error,
+}
+
+impl Outcome {
+    // What a database record says about its file
+    pub fn of(status: Status) -> Outcome {
+        match status {
+            Status::HasOrigin => Outcome::Origin,
+            // A file never analysed can't pass the gate
+            Status::Error | Status::Timeout | Status::Unscanned => Outcome::Error,
+            Status::NoOrigin | Status::Deleted => Outcome::Clean,
+        }
+    }
+
+    pub fn code(self) -> ExitCode {
+        match self {
+            Outcome::Clean => ExitCode::SUCCESS,
+            Outcome::Origin => ExitCode::from(1),
+            Outcome::Error => ExitCode::from(2),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//Machine readable exports of the database records: JSON, NDJSON and CSV
+use std::io::{self, Write};
+use crate::record::{FileRecord, TrackOrigin};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    // One array holding every file
+    Json,
+    // One object per line and file
+    Ndjson,
+    // One row per Origin, files without Origin get a single row
+    Csv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "ndjson" => Some(Format::Ndjson),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+const CSV_COLUMNS: [&str; 13] = [
+    "path", "status", "file_size", "analysed_at", "elapsed_ms", "error",
+    "partition", "package", "track_id", "track_name", "edit_rate", "origin", "origin_offset",
+];
+
+// Write the records in a machine readable format, Text is left to the report
+pub fn write_records<W: Write>(out: &mut W, format: Format, records: &[(String, FileRecord)]) -> io::Result<()> {
+    match format {
+        Format::Text => {}
+        Format::Json => {
+            writeln!(out, "[")?;
+            for (index, (file_path, filerecord)) in records.iter().enumerate() {
+                let separator = if index + 1 < records.len() { "," } else { "" };
+                writeln!(out, "  {}{}", json_record(file_path, filerecord), separator)?;
+            }
+            writeln!(out, "]")?;
+        }
+        Format::Ndjson => {
+            for (file_path, filerecord) in records {
+                writeln!(out, "{}", json_record(file_path, filerecord))?;
+            }
+        }
+        Format::Csv => {
+            writeln!(out, "{}", CSV_COLUMNS.join(","))?;
+            for (file_path, filerecord) in records {
+                for row in csv_rows(file_path, filerecord) {
+                    writeln!(out, "{}", row)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// One JSON object for a file and its Origins
+fn json_record(file_path: &str, filerecord: &FileRecord) -> String {
+    let origins: Vec<String> = filerecord.origins.iter().map(json_origin).collect();
+    format!(
+        "{{\"path\":{},\"status\":{},\"file_size\":{},\"mtime\":{},\"analysed_at\":{},\"elapsed_ms\":{},\"deleted_at\":{},\"dumper_version\":{},\"error\":{},\"origins\":[{}]}}",
+        json_string(file_path),
+        json_string(filerecord.status.name()),
+        filerecord.file_size,
+        filerecord.mtime,
+        filerecord.analysed_at,
+        filerecord.elapsed_ms,
+        filerecord.deleted_at,
+        json_string(&filerecord.dumper_version),
+        filerecord.error.as_deref().map_or("null".to_string(), json_string),
+        origins.join(",")
+    )
+}
+
+fn json_origin(origin: &TrackOrigin) -> String {
+    format!(
+        "{{\"partition\":{},\"package\":{},\"track_id\":{},\"track_name\":{},\"edit_rate\":{},\"origin\":{},\"origin_offset\":{}}}",
+        json_string(origin.partition.name()),
+        json_string(origin.package.name()),
+        origin.track_id.map_or("null".to_string(), |id| id.to_string()),
+        origin.track_name.as_deref().map_or("null".to_string(), json_string),
+        origin.edit_rate.map_or("null".to_string(), |rate| json_string(&rate.to_string())),
+        origin.origin,
+        origin.origin_offset.map_or("null".to_string(), |offset| offset.to_string())
+    )
+}
+
+// Quote and escape a JSON string
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn csv_rows(file_path: &str, filerecord: &FileRecord) -> Vec<String> {
+    let file = [
+        csv_field(file_path),
+        csv_field(filerecord.status.name()),
+        filerecord.file_size.to_string(),
+        filerecord.analysed_at.to_string(),
+        filerecord.elapsed_ms.to_string(),
+        csv_field(filerecord.error.as_deref().unwrap_or("")),
+    ]
+    .join(",");
+    if filerecord.origins.is_empty() {
+        return vec![format!("{},,,,,,,", file)];
+    }
+    filerecord
+        .origins
+        .iter()
+        .map(|origin| {
+            format!(
+                "{},{},{},{},{},{},{},{}",
+                file,
+                csv_field(origin.partition.name()),
+                csv_field(origin.package.name()),
+                origin.track_id.map_or(String::new(), |id| id.to_string()),
+                csv_field(origin.track_name.as_deref().unwrap_or("")),
+                origin.edit_rate.map_or(String::new(), |rate| rate.to_string()),
+                origin.origin,
+                origin.origin_offset.map_or(String::new(), |offset| offset.to_string())
+            )
+        })
+        .collect()
+}
+
+// Quote a CSV field when it holds a separator, a quote or a line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fingerprint::Fingerprint;
+    use crate::metadata::PackageKind;
+    use crate::partition::PartitionKind;
+    use crate::record::Status;
+
+    fn records(count: usize) -> Vec<(String, FileRecord)> {
+        let fingerprint = Fingerprint { file_size: 1024, mtime: 0, content_hash: None };
+        (0..count).map(|index| (format!("/media/clip{}.mxf", index), FileRecord::unscanned(&fingerprint))).collect()
+    }
+
+    fn origin() -> TrackOrigin {
+        TrackOrigin {
+            partition: PartitionKind::Footer,
+            package: PackageKind::Material,
+            track_id: Some(1),
+            track_name: None,
+            edit_rate: None,
+            origin: -16,
+            origin_offset: Some(0x571ca3),
+        }
+    }
+
+    fn written(format: Format, records: &[(String, FileRecord)]) -> String {
+        let mut out = Vec::new();
+        write_records(&mut out, format, records).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("say \"hi\""), r#""say \"hi\"""#);
+        assert_eq!(json_string("E:\\Source\\a.mxf"), r#""E:\\Source\\a.mxf""#);
+        assert_eq!(json_string("a\nb\r\tc\u{1}"), r#""a\nb\r\tc\u0001""#);
+        assert_eq!(json_string("caf\u{e9}"), "\"caf\u{e9}\"");
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn csv_rows_have_every_column() {
+        let mut records = records(2);
+        records[1].1.status = Status::HasOrigin;
+        records[1].1.origins = vec![origin(), origin()];
+        let text = written(Format::Csv, &records);
+        let lines: Vec<&str> = text.lines().collect();
+        // Header, one row for the file without Origin, one per Origin of the other
+        assert_eq!(lines.len(), 4);
+        for line in lines {
+            assert_eq!(line.split(',').count(), CSV_COLUMNS.len(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn json_array_separates_the_records() {
+        assert_eq!(written(Format::Json, &[]), "[\n]\n");
+        let one = written(Format::Json, &records(1));
+        assert!(one.starts_with("[\n  {\"path\":\"/media/clip0.mxf\"") && one.ends_with("\"origins\":[]}\n]\n"));
+        let text = written(Format::Json, &records(3));
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].ends_with("},") && lines[2].ends_with("},") && lines[3].ends_with('}'));
+        // NDJSON has no separators at all
+        assert!(written(Format::Ndjson, &records(3)).lines().all(|line| line.starts_with('{') && line.ends_with('}')));
+    }
+}
@@ -0,0 +1,87 @@
+//File fingerprint used to skip files that didn't change since their last analysis
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use crate::record::{self, FileRecord};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub file_size: u64,
+    // Seconds since the Unix epoch
+    pub mtime: u64,
+    // Only computed on request, reading whole MXF files is slow
+    pub content_hash: Option<u64>,
+}
+
+impl Fingerprint {
+    pub fn of(path: &Path, with_hash: bool) -> io::Result<Fingerprint> {
+        let meta = fs::metadata(path)?;
+        let content_hash = if with_hash { Some(content_hash(path)?) } else { None };
+        Ok(Fingerprint {
+            file_size: meta.len(),
+            mtime: meta.modified().map_or(0, record::to_epoch),
+            content_hash,
+        })
+    }
+
+    // Size and mtime must match, the hashes only count when both sides have one
+    pub fn matches(&self, record: &FileRecord) -> bool {
+        if self.file_size != record.file_size || self.mtime != record.mtime {
+            return false;
+        }
+        match (self.content_hash, record.content_hash) {
+            (Some(ours), Some(theirs)) => ours == theirs,
+            _ => true,
+        }
+    }
+}
+
+// 64-bit FNV-1a of the whole file content
+pub fn content_hash(path: &Path) -> io::Result<u64> {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buffer = [0u8; 64 * 1024];
+    let mut hash = OFFSET_BASIS;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        for byte in &buffer[..read] {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(file_size: u64, mtime: u64, content_hash: Option<u64>) -> Fingerprint {
+        Fingerprint { file_size, mtime, content_hash }
+    }
+
+    #[test]
+    fn size_and_mtime_must_match() {
+        let filerecord = FileRecord::unscanned(&fingerprint(1024, 100, None));
+        assert!(fingerprint(1024, 100, None).matches(&filerecord));
+        assert!(!fingerprint(1025, 100, None).matches(&filerecord));
+        assert!(!fingerprint(1024, 101, None).matches(&filerecord));
+    }
+
+    #[test]
+    fn hashes_only_count_when_both_sides_have_one() {
+        let hashed = FileRecord::unscanned(&fingerprint(1024, 100, Some(7)));
+        let unhashed = FileRecord::unscanned(&fingerprint(1024, 100, None));
+        assert!(fingerprint(1024, 100, Some(7)).matches(&hashed));
+        assert!(!fingerprint(1024, 100, Some(8)).matches(&hashed));
+        assert!(fingerprint(1024, 100, None).matches(&hashed));
+        assert!(fingerprint(1024, 100, Some(8)).matches(&unhashed));
+        // The hash doesn't make up for a size change
+        assert!(!fingerprint(1025, 100, Some(7)).matches(&hashed));
+    }
+}
@@ -0,0 +1,359 @@
+//In place repair of the Origin values found by the analysis
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Instant;
+use clap::ArgMatches;
+use sled::Db;
+use crate::analyze::{self, Analysis, OriginPolicy};
+use crate::cli;
+use crate::dumper::{self, Dumper};
+use crate::exit::Outcome;
+use crate::fingerprint::Fingerprint;
+use crate::metadata::Position;
+use crate::output::Output;
+use crate::record::{self, FileRecord, Status, TrackOrigin};
+
+#[derive(Debug, Clone, Copy)]
+struct Options {
+    // Package types whose Origin is zeroed: material, source or both
+    policy: OriginPolicy,
+    // Also copy the whole file before writing, the journal alone holds the old values
+    backup: bool,
+    // Only print what would be written
+    dry_run: bool,
+    verbose: bool,
+}
+
+// One Origin value to overwrite with zero
+struct Patch {
+    offset: u64,
+    origin: Position,
+    location: String,
+}
+
+// Zero the non-zero Origins recorded for the given files, or for every HasOrigin file
+pub fn run_fix(args: &ArgMatches) -> io::Result<Outcome> {
+    let options = Options {
+        policy: cli::origin_policy(args),
+        backup: args.is_present("backup"),
+        dry_run: args.is_present("dry-run"),
+        verbose: args.is_present("verbose"),
+    };
+
+    let db = record::open_db()?;
+    let mut targets = Vec::new();
+    match args.values_of("paths") {
+        Some(paths) => {
+            for file_path in paths {
+                let (file_path, key) = record::key_of(file_path);
+                match db.get(key.as_bytes()) {
+                    Ok(Some(value)) => targets.push((key, file_path, record::load(&value))),
+                    Ok(None) => println!("{} : not in the database, scan and analyze it first", file_path),
+                    Err(e) => eprintln!("Error reading {} from the database : {}", file_path, e),
+                }
+            }
+        }
+        None => {
+            for (key, value) in db.iter().flatten() {
+                let filerecord = record::load(&value);
+                if filerecord.status == Status::HasOrigin
+                    && let Ok(key) = String::from_utf8(key.to_vec())
+                    && let Ok(file_path) = record::decodeb64(&key)
+                {
+                    targets.push((key, file_path, filerecord));
+                }
+            }
+        }
+    }
+
+    let mut outcome = Outcome::Clean;
+    for (key, file_path, filerecord) in targets {
+        println!("\n--- FIXING MXF file {} ---", file_path);
+        match fix_file(&db, &key, &file_path, &filerecord, options) {
+            Ok(file_outcome) => outcome = outcome.max(file_outcome),
+            Err(e) => {
+                eprintln!("Error fixing {} : {}", file_path, e);
+                outcome = Outcome::Error;
+            }
+        }
+    }
+    db.flush()?;
+    Ok(outcome)
+}
+
+// Zero the recorded Origins of one file, then analyse it again and save the result
+fn fix_file(db: &Db, key: &str, file_path: &str, filerecord: &FileRecord, options: Options) -> io::Result<Outcome> {
+    let Options { policy, backup, dry_run, verbose } = options;
+    if filerecord.status != Status::HasOrigin {
+        println!("Nothing to fix, the file is {}.", filerecord.status.name());
+        return Ok(Outcome::of(filerecord.status));
+    }
+    // The offsets are only valid for the bytes that were analysed
+    let fingerprint = Fingerprint::of(Path::new(file_path), filerecord.content_hash.is_some())?;
+    if !fingerprint.matches(filerecord) {
+        return Err(invalid("the file changed since its analysis, scan and analyze it again".to_string()));
+    }
+
+    let mut patches = Vec::new();
+    for origin in filerecord.origins.iter().filter(|o| o.origin != 0 && policy.applies_to(o.package)) {
+        match origin.origin_offset {
+            Some(offset) => patches.push(Patch { offset, origin: origin.origin, location: describe(origin) }),
+            None => {
+                return Err(invalid(format!(
+                    "no offset recorded for the Origin of {}, analyze the file again with the native or mxfdump dumper",
+                    describe(origin)
+                )));
+            }
+        }
+    }
+    if patches.is_empty() {
+        println!("No non-zero Origin in the package types to fix.");
+        return Ok(Outcome::Origin);
+    }
+
+    let journal_path = format!("{}.origin-journal", file_path);
+    if dry_run {
+        for patch in &patches {
+            println!("Would zero Origin = {} at {:#x} in {}", patch.origin, patch.offset, patch.location);
+        }
+        return Ok(Outcome::Origin);
+    }
+    if backup {
+        let backup_path = format!("{}.bak", file_path);
+        fs::copy(file_path, &backup_path)?;
+        println!("Backup copy written to {}", backup_path);
+    }
+    write_patches(file_path, &journal_path, &patches)?;
+
+    // Confirm on a full walk, so that every header metadata copy is seen
+    let started = Instant::now();
+    let mut out = Output::default();
+    let mut analysis = analyze::analyze_file(file_path, verbose, false, false, None, &mut out)?;
+    // Less authoritative copies, e.g. the header of a file whose footer was read, keep
+    // their own Origin values, and a reader falling back on them would still skip frames
+    let stale = stale_copies(&analysis, policy);
+    if !stale.is_empty() {
+        write_patches(file_path, &journal_path, &stale)?;
+        out = Output::default();
+        analysis = analyze::analyze_file(file_path, verbose, false, false, None, &mut out)?;
+    }
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    out.print();
+
+    let mut updated = filerecord.clone();
+    let fingerprint = Fingerprint::of(Path::new(file_path), filerecord.content_hash.is_some())?;
+    updated.file_size = fingerprint.file_size;
+    updated.mtime = fingerprint.mtime;
+    updated.content_hash = fingerprint.content_hash;
+    updated.analysed_at = record::now();
+    updated.elapsed_ms = elapsed_ms;
+    updated.dumper_version = dumper::Native.name();
+    updated.argv = dumper::Native.argv(file_path);
+    // Same rule as the analysis, with its policy rather than the package types fixed
+    updated.status = if analysis.has_origin(filerecord.policy) { Status::HasOrigin } else { Status::NoOrigin };
+    updated.origins = analysis.origins.iter().map(TrackOrigin::from_finding).collect();
+    updated.error = (!analysis.errors.is_empty()).then(|| analysis.errors.join("; "));
+    db.insert(key.as_bytes(), updated.encode())?;
+
+    let left = stale_copies(&analysis, policy).len() + analysis.origins.iter().filter(|o| o.is_flagged(policy)).count();
+    if left > 0 {
+        eprintln!("{} non-zero Origin(s) left after the fix, the old values are in {}", left, journal_path);
+        return Ok(Outcome::Error);
+    }
+    println!("Zeroed {} Origin value(s), the old values are in {}", patches.len() + stale.len(), journal_path);
+    if updated.status == Status::HasOrigin {
+        println!("Non-zero Origins are left in the package types not fixed, the file is still HasOrigin.");
+    }
+    Ok(Outcome::of(updated.status))
+}
+
+// Check every value first, then journal the old bytes, then write the zeros
+fn write_patches(file_path: &str, journal_path: &str, patches: &[Patch]) -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(file_path)?;
+    for patch in patches {
+        // 2-byte length of the local item, then the 8-byte Origin value
+        let mut bytes = [0u8; 10];
+        file.seek(SeekFrom::Start(patch.offset.saturating_sub(2)))?;
+        file.read_exact(&mut bytes)?;
+        if bytes[..2] != [0, 8] || bytes[2..] != patch.origin.to_be_bytes() {
+            return Err(invalid(format!(
+                "expected Origin = {} at {:#x} in {}, found {:02x?}, nothing was written",
+                patch.origin, patch.offset, patch.location, &bytes[2..]
+            )));
+        }
+    }
+
+    // One line per value, enough to restore it with a hex editor
+    let mut journal = OpenOptions::new().create(true).append(true).open(journal_path)?;
+    writeln!(journal, "# {} at {}", file_path, record::now())?;
+    for patch in patches {
+        writeln!(journal, "{:#x} {:016x} {}", patch.offset, patch.origin, patch.location)?;
+    }
+    journal.sync_all()?;
+
+    for patch in patches {
+        file.seek(SeekFrom::Start(patch.offset))?;
+        file.write_all(&[0u8; 8])?;
+        println!("Zeroed Origin = {} at {:#x} in {}", patch.origin, patch.offset, patch.location);
+    }
+    file.sync_all()
+}
+
+// Non-zero Origins of the metadata copies the resolution didn't pick
+fn stale_copies(analysis: &Analysis, policy: OriginPolicy) -> Vec<Patch> {
+    let mut patches = Vec::new();
+    for (index, metadata) in analysis.metadata.iter().enumerate() {
+        if Some(index) == analysis.source {
+            continue;
+        }
+        for package in metadata.packages().unwrap_or_default() {
+            if !policy.applies_to(package.kind) {
+                continue;
+            }
+            for track in &package.tracks {
+                if let (Some(origin), Some(offset)) = (track.origin, track.origin_offset)
+                    && origin != 0
+                {
+                    let location = format!(
+                        "{} partition, {} / track {}",
+                        metadata.partition.name(),
+                        package.kind.name(),
+                        track.track_id.map_or("?".to_string(), |id| id.to_string())
+                    );
+                    patches.push(Patch { offset, origin, location });
+                }
+            }
+        }
+    }
+    patches
+}
+
+fn describe(origin: &TrackOrigin) -> String {
+    format!(
+        "{} partition, {} / track {}",
+        origin.partition.name(),
+        origin.package.name(),
+        origin.track_id.map_or("?".to_string(), |id| id.to_string())
+    )
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::PackageKind;
+    use crate::testmxf::{self, MxfFile};
+
+    // Origin local item, tag 4b.02 and length 8, after two bytes of something else
+    fn track_bytes(origin: Position) -> Vec<u8> {
+        [vec![0xaa, 0xbb, 0x4b, 0x02, 0x00, 0x08], origin.to_be_bytes().to_vec()].concat()
+    }
+
+    #[test]
+    fn only_the_expected_values_are_zeroed() {
+        let dir = std::env::temp_dir().join(format!("whereismyorigin-fix-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("track.mxf").to_string_lossy().into_owned();
+        let journal_path = format!("{}.origin-journal", file_path);
+        fs::write(&file_path, track_bytes(16)).unwrap();
+        let patch = |origin| Patch { offset: 6, origin, location: "test".to_string() };
+
+        // A value that isn't the recorded one is left alone, and nothing is journaled
+        assert!(write_patches(&file_path, &journal_path, &[patch(25)]).is_err());
+        assert_eq!(fs::read(&file_path).unwrap(), track_bytes(16));
+        assert!(!Path::new(&journal_path).exists());
+
+        write_patches(&file_path, &journal_path, &[patch(16)]).unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), track_bytes(0));
+        assert!(fs::read_to_string(&journal_path).unwrap().contains("0x6 0000000000000010 test"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Closed complete header and footer copies of the same metadata, the footer one is
+    // authoritative and the header one stale. Returns the path, its key and record in a new database
+    fn analysed_file(name: &str) -> (String, String, FileRecord, Db) {
+        let path = MxfFile::default()
+            .partition(testmxf::HEADER, true, true)
+            .metadata(16, 16)
+            .essence(256)
+            .partition(testmxf::FOOTER, true, true)
+            .metadata(16, 16)
+            .link_footer()
+            .write(name);
+        let analysis = analyze::analyze_file(&path, false, false, false, None, &mut Output::default()).unwrap();
+        let mut filerecord = FileRecord::unscanned(&Fingerprint::of(Path::new(&path), false).unwrap());
+        filerecord.status = Status::HasOrigin;
+        filerecord.origins = analysis.origins.iter().map(TrackOrigin::from_finding).collect();
+        let (path, key) = record::key_of(&path);
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        db.insert(key.as_bytes(), filerecord.encode()).unwrap();
+        (path, key, filerecord, db)
+    }
+
+    fn options(policy: OriginPolicy, dry_run: bool) -> Options {
+        Options { policy, backup: false, dry_run, verbose: false }
+    }
+
+    fn remove(path: &str) {
+        fs::remove_file(path).unwrap();
+        let _ = fs::remove_file(format!("{}.origin-journal", path));
+    }
+
+    #[test]
+    fn every_copy_is_zeroed_and_the_record_updated() {
+        let (path, key, filerecord, db) = analysed_file("fix.mxf");
+        assert_eq!(fix_file(&db, &key, &path, &filerecord, options(OriginPolicy::Both, false)).unwrap(), Outcome::Clean);
+
+        let analysis = analyze::analyze_file(&path, false, false, false, None, &mut Output::default()).unwrap();
+        for metadata in &analysis.metadata {
+            let tracks = metadata.packages().unwrap().into_iter().flat_map(|package| package.tracks);
+            assert!(tracks.map(|track| track.origin).all(|origin| origin == Some(0)), "{} copy", metadata.partition.name());
+        }
+        let updated = record::load(&db.get(&key).unwrap().unwrap());
+        assert_eq!(updated.status, Status::NoOrigin);
+        assert_eq!(updated.dumper_version, dumper::Native.name());
+        assert!(updated.origins.iter().all(|origin| origin.origin == 0 && origin.origin_offset.is_some()));
+        assert_eq!(fs::read_to_string(format!("{}.origin-journal", path)).unwrap().lines().filter(|line| !line.starts_with('#')).count(), 4);
+        remove(&path);
+    }
+
+    #[test]
+    fn source_origins_keep_the_file_flagged_after_a_material_fix() {
+        let (path, key, filerecord, db) = analysed_file("fix-material.mxf");
+        assert_eq!(fix_file(&db, &key, &path, &filerecord, options(OriginPolicy::Material, false)).unwrap(), Outcome::Origin);
+        let updated = record::load(&db.get(&key).unwrap().unwrap());
+        assert_eq!(updated.status, Status::HasOrigin);
+        let left: Vec<PackageKind> = updated.origins.iter().filter(|origin| origin.origin != 0).map(|origin| origin.package).collect();
+        assert_eq!(left, [PackageKind::Source]);
+        remove(&path);
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let (path, key, filerecord, db) = analysed_file("fix-dry-run.mxf");
+        let before = fs::read(&path).unwrap();
+        assert_eq!(fix_file(&db, &key, &path, &filerecord, options(OriginPolicy::Both, true)).unwrap(), Outcome::Origin);
+        assert_eq!(fs::read(&path).unwrap(), before);
+        assert!(!Path::new(&format!("{}.origin-journal", path)).exists());
+        assert_eq!(record::load(&db.get(&key).unwrap().unwrap()), filerecord);
+        remove(&path);
+    }
+
+    #[test]
+    fn changed_file_is_refused() {
+        let (path, key, filerecord, db) = analysed_file("fix-changed.mxf");
+        // Another copy written over the analysed one, one byte longer
+        let mut changed = fs::read(&path).unwrap();
+        changed.push(0);
+        fs::write(&path, &changed).unwrap();
+        let error = fix_file(&db, &key, &path, &filerecord, options(OriginPolicy::Both, false)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), changed);
+        assert!(!Path::new(&format!("{}.origin-journal", path)).exists());
+        remove(&path);
+    }
+}
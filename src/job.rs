@@ -0,0 +1,244 @@
+//Per file analysis jobs
+use std::io;
+use std::time::Instant;
+use sled::Db;
+use crate::analyze::{Analysis, OriginKind, OriginPolicy};
+use crate::dumper::{Dumper, Options};
+use crate::output::Output;
+use crate::partition::{self, PartitionKind, PartitionPack};
+use crate::record::{self, FileRecord, Status, TrackOrigin};
+
+// One file to analyse, each job builds its own dumper invocation when it runs
+#[derive(Debug, Clone)]
+pub struct Job {
+    // Database key, the base64 of the path
+    pub key: String,
+    pub path: String,
+    // Record before the analysis
+    pub record: FileRecord,
+}
+
+// The files of the database that need an analysis, and how many were skipped.
+// The scan resets modified files to Unscanned, the others were already analysed
+// unless `force` asks for everything again.
+pub fn pending(db: &Db, force: bool, verbose: bool) -> (Vec<Job>, usize) {
+    let mut skipped = 0;
+    let mut todo = Vec::new();
+    for result in db.iter() {
+        match result {
+            Ok((key_bytes, value_bytes)) => {
+                if let Ok(videofilepathb64) = String::from_utf8(key_bytes.to_vec()) {
+                    let videofilepath = match record::decodeb64(&videofilepathb64) {
+                        Ok(val) => val,
+                        Err(e) => {
+                            eprintln!("Error decoding {} : {}", &videofilepathb64, e);
+                            continue;
+                        }
+                    };
+                    let filerecord = record::load(&value_bytes);
+                    if verbose {
+                        println!("This is the path I got: {}",&videofilepath);
+                        println!("Previous status: {}", filerecord.status.name());
+                    }
+                    if filerecord.status == Status::Deleted || (filerecord.status != Status::Unscanned && !force) {
+                        skipped += 1;
+                        continue;
+                    }
+                    todo.push(Job { key: videofilepathb64, path: videofilepath, record: filerecord });
+                } else {
+                    eprintln!("couldn't decode the key");
+                }
+            }
+            Err(e) => {
+                eprintln!("Error during iteration {e}");
+            }
+        }
+    }
+    (todo, skipped)
+}
+
+impl Job {
+    // Analyse the file and return its updated record, the console output goes to `out`
+    pub fn run(&self, dumper: &dyn Dumper, options: &Options, policy: OriginPolicy, out: &mut Output) -> FileRecord {
+        let videofilepath = self.path.as_str();
+        let mut filerecord = self.record.clone();
+        let verbose = options.verbose;
+        out.line("\n--- PROCESSING MXF file ---".to_string());
+        filerecord.analysed_at = record::now();
+        filerecord.dumper_version = dumper.name();
+        filerecord.error = None;
+        // Built for this file alone, and kept so that the exact run can be replayed
+        filerecord.argv = dumper.argv(videofilepath);
+
+        let started = Instant::now();
+        let result = dumper.analyze(&filerecord.argv, options, out);
+        filerecord.elapsed_ms = started.elapsed().as_millis() as u64;
+        let analysis = match result {
+            Ok(analysis) => analysis,
+            Err(e) => {
+                out.error(format!("Error reading {} : {}", videofilepath, e));
+                // A hung dumper is told apart from a file it failed on
+                filerecord.status = if e.kind() == io::ErrorKind::TimedOut { Status::Timeout } else { Status::Error };
+                filerecord.error = Some(e.to_string());
+                filerecord.origins.clear();
+                return filerecord;
+            }
+        };
+
+        // Save the result, HasOrigin when a non-zero Origin/Precharge is flagged by the policy
+        filerecord.status = if analysis.has_origin(policy) { Status::HasOrigin } else { Status::NoOrigin };
+        filerecord.policy = policy;
+        filerecord.origins = analysis.origins.iter().map(TrackOrigin::from_finding).collect();
+        if !analysis.errors.is_empty() {
+            filerecord.error = Some(analysis.errors.join("; "));
+        }
+
+        print_analysis(videofilepath, &analysis, policy, verbose, out);
+        filerecord
+    }
+}
+
+// Print what the analysis of a file or of a dump found, the console output goes to `out`
+pub fn print_analysis(videofilepath: &str, analysis: &Analysis, policy: OriginPolicy, verbose: bool, out: &mut Output) {
+    // Print the partition status, open or incomplete headers come from crashed recorders
+    out.line("\n--- Partitions ---".to_string());
+    match analysis.header_partition() {
+        Some(header) => print_partition(header, verbose, out),
+        None => out.line("No header partition pack found.".to_string()),
+    }
+    match analysis.footer_partition() {
+        Some(footer) => print_partition(footer, verbose, out),
+        None => out.line("No footer partition pack found.".to_string()),
+    }
+
+    if verbose && let Some(rip) = &analysis.rip {
+        out.line(format!("Random Index Pack ( {:016x} ) lists {} partition(s)", rip.offset, rip.entries.len()));
+    }
+    // Say which copy the values come from when the header isn't the only one
+    if let Some(source) = analysis.source_metadata()
+        && analysis.metadata.len() > 1
+    {
+        out.line(format!(
+            "Origin values taken from the {} partition metadata ( {:016x} ), {} and {}, the most authoritative of {} copies",
+            source.partition.name(),
+            source.partition_offset,
+            if source.closed { "closed" } else { "open" },
+            if source.complete { "complete" } else { "incomplete" },
+            analysis.metadata.len()
+        ));
+    }
+
+    for error in &analysis.errors {
+        out.error(format!("Header metadata error in {} : {}", videofilepath, error));
+    }
+
+    let matches = &analysis.origins;
+    // Print Origin values, a zero Origin is the same as no Origin
+    out.line("\n--- Looking for Origin/Precharge ---".to_string());
+    if matches.is_empty() {
+        out.line("No Origin property found.".to_string());
+    } else {
+        let non_zero = matches.iter().filter(|m| m.kind() != OriginKind::Zero).count();
+        let flagged = matches.iter().filter(|m| m.is_flagged(policy)).count();
+        out.line(format!("Found {} Origin propert(ies), {} non-zero, {} flagged.", matches.len(), non_zero, flagged));
+        for (idx, m) in matches.iter().enumerate() {
+            if verbose || m.kind() != OriginKind::Zero {
+                let mark = if m.is_flagged(policy) { "" } else { " [not flagged]" };
+                out.line(format!("Match #{}: Origin = {} ({}) in {}{}", idx + 1, m.time(), m.kind().name(), m.location(), mark));
+            }
+        }
+    }
+    if analysis.has_origin(policy) {
+        out.line("With origin\n".to_string());
+    } else {
+        out.line("No origin\n".to_string());
+    }
+}
+
+fn print_partition(partition: &PartitionPack, verbose: bool, out: &mut Output) {
+    out.line(format!("{} partition: {}", partition.kind.name(), partition.status()));
+    if partition.kind == PartitionKind::Header && !(partition.closed && partition.complete) {
+        out.line("Warning: the header partition is not closed and complete, the file may not have been finalised".to_string());
+    }
+    if verbose {
+        out.line(format!("       ThisPartition = {:016x}", partition.this_partition));
+        out.line(format!("   PreviousPartition = {:016x}", partition.previous_partition));
+        out.line(format!("     FooterPartition = {:016x}", partition.footer_partition));
+        out.line(format!("     HeaderByteCount = {:016x}", partition.header_byte_count));
+        out.line(format!("      IndexByteCount = {:016x}", partition.index_byte_count));
+        out.line(format!("             KAGSize = {:08x}", partition.kag_size));
+        out.line(format!("            IndexSID = {:08x}", partition.index_sid));
+        out.line(format!("             BodySID = {:08x}", partition.body_sid));
+        out.line(format!(" Operational Pattern = {}", partition::operational_pattern_name(&partition.operational_pattern)));
+        out.line(format!("   EssenceContainers = {}", partition.essence_containers.len()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::worker;
+
+    fn job(path: &str) -> Job {
+        Job { key: base64::encode(path), path: path.to_string(), record: record::load(&[]) }
+    }
+
+    // In-process backend remembering every invocation it was asked to analyse
+    #[derive(Default)]
+    struct Recorder {
+        seen: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl Dumper for Recorder {
+        fn name(&self) -> String {
+            "recorder".to_string()
+        }
+
+        fn argv(&self, videofilepath: &str) -> Vec<String> {
+            vec!["recorder".to_string(), videofilepath.to_string()]
+        }
+
+        fn analyze(&self, argv: &[String], _options: &Options, _out: &mut Output) -> io::Result<Analysis> {
+            self.seen.lock().unwrap().push(argv.to_vec());
+            Ok(Analysis::default())
+        }
+    }
+
+    fn run(dumper: &dyn Dumper, job: &Job) -> FileRecord {
+        job.run(dumper, &Options::default(), OriginPolicy::Both, &mut Output::default())
+    }
+
+    #[test]
+    fn second_file_is_never_analysed_with_the_first_path() {
+        let dumper = Recorder::default();
+        let first = run(&dumper, &job("/media/first.mxf"));
+        let second = run(&dumper, &job("/media/second.mxf"));
+
+        assert_eq!(first.argv, ["recorder", "/media/first.mxf"]);
+        assert_eq!(second.argv, ["recorder", "/media/second.mxf"]);
+        let seen = dumper.seen.lock().unwrap();
+        assert_eq!(*seen, [first.argv.clone(), second.argv.clone()]);
+    }
+
+    #[test]
+    fn parallel_jobs_record_their_own_argv() {
+        let dumper = Recorder::default();
+        let jobs: Vec<Job> = (0..64).map(|i| job(&format!("/media/clip{}.mxf", i))).collect();
+        let mut records = Vec::new();
+        worker::run_ordered(jobs.clone(), 8, |job| (job.path.clone(), run(&dumper, job)), |result| records.push(result));
+
+        assert_eq!(records.len(), jobs.len());
+        for (path, filerecord) in &records {
+            assert_eq!(filerecord.argv, ["recorder", path.as_str()]);
+        }
+        assert_eq!(dumper.seen.lock().unwrap().len(), jobs.len());
+    }
+
+    #[test]
+    fn argv_survives_the_database_record() {
+        let filerecord = run(&Recorder::default(), &job("/media/first.mxf"));
+        let decoded = FileRecord::decode(&filerecord.encode()).unwrap();
+        assert_eq!(decoded.argv, ["recorder", "/media/first.mxf"]);
+    }
+}
@@ -0,0 +1,264 @@
+//KLV (Key-Length-Value) reader for MXF files
+use std::io::{self, Read, Seek, SeekFrom};
+
+// A SMPTE Universal Label, the 16-byte key of every KLV packet
+pub type UL = [u8; 16];
+
+// All SMPTE labels start with this prefix
+pub const UL_PREFIX: [u8; 4] = [0x06, 0x0e, 0x2b, 0x34];
+
+// One KLV packet as found in the file, the value itself is not read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Klv {
+    pub key: UL,
+    pub length: u64,
+    // Absolute file offset of the first byte of the key
+    pub offset: u64,
+    // Absolute file offset of the first byte of the value
+    pub value_offset: u64,
+}
+
+impl Klv {
+    // Number of bytes used by the BER length field (MXFDump's LL)
+    pub fn length_size(&self) -> u64 {
+        self.value_offset - self.offset - 16
+    }
+}
+
+// Iterates over the KLV packets of a file, seeking over the values
+pub struct KlvReader<R> {
+    reader: R,
+    // Offset of the next packet to return
+    position: u64,
+    // Offset the underlying reader is at, to skip seeks that would drop its buffer
+    stream_position: Option<u64>,
+    // Bounds the values read, see read_value
+    file_size: Option<u64>,
+    failed: bool,
+}
+
+impl<R: Read + Seek> KlvReader<R> {
+    pub fn new(reader: R) -> KlvReader<R> {
+        KlvReader { reader, position: 0, stream_position: None, file_size: None, failed: false }
+    }
+
+    // Continue iterating at an absolute file offset (e.g. the footer partition)
+    pub fn seek(&mut self, offset: u64) {
+        self.position = offset;
+        self.failed = false;
+    }
+
+    // Access the underlying reader, e.g. to read the end of the file
+    pub fn get_mut(&mut self) -> &mut R {
+        self.stream_position = None;
+        &mut self.reader
+    }
+
+    // Read the whole value of a packet returned by this reader
+    pub fn read_value(&mut self, klv: &Klv) -> io::Result<Vec<u8>> {
+        // The length comes from the file, don't allocate more than the file holds
+        let file_size = self.file_size()?;
+        let end = klv.value_offset.checked_add(klv.length);
+        if end.is_none_or(|end| end > file_size) {
+            return Err(invalid_data(format!(
+                "KLV value of {} bytes at offset {:#x} runs past the end of the file",
+                klv.length, klv.offset
+            )));
+        }
+        let length = usize::try_from(klv.length).map_err(|_| invalid_data("KLV value too large to read".to_string()))?;
+        self.seek_stream(klv.value_offset)?;
+        let mut value = vec![0; length];
+        self.stream_position = None;
+        self.reader.read_exact(&mut value)?;
+        self.stream_position = Some(klv.value_offset + klv.length);
+        Ok(value)
+    }
+
+    // Size of the file, looked up once
+    fn file_size(&mut self) -> io::Result<u64> {
+        if self.file_size.is_none() {
+            self.stream_position = None;
+            self.file_size = Some(self.reader.seek(SeekFrom::End(0))?);
+        }
+        Ok(self.file_size.unwrap_or(u64::MAX))
+    }
+
+    fn seek_stream(&mut self, offset: u64) -> io::Result<()> {
+        if self.stream_position != Some(offset) {
+            self.stream_position = None;
+            self.reader.seek(SeekFrom::Start(offset))?;
+            self.stream_position = Some(offset);
+        }
+        Ok(())
+    }
+
+    fn read_klv(&mut self) -> io::Result<Option<Klv>> {
+        self.seek_stream(self.position)?;
+        // Until the packet is fully read the stream position is unknown
+        self.stream_position = None;
+
+        let mut key = [0u8; 16];
+        let read = read_full(&mut self.reader, &mut key)?;
+        if read == 0 {
+            return Ok(None); // End of file
+        }
+        if read < key.len() {
+            return Err(invalid_data(format!("truncated KLV key at offset {:#x}", self.position)));
+        }
+        if key[0..4] != UL_PREFIX {
+            return Err(invalid_data(format!("no SMPTE label at offset {:#x}", self.position)));
+        }
+
+        let (length, length_size) = read_ber_length(&mut self.reader)?;
+        let offset = self.position;
+        let value_offset = offset + 16 + length_size;
+        self.stream_position = Some(value_offset);
+        self.position = value_offset
+            .checked_add(length)
+            .ok_or_else(|| invalid_data(format!("KLV length overflow at offset {:#x}", offset)))?;
+
+        Ok(Some(Klv { key, length, offset, value_offset }))
+    }
+}
+
+impl<R: Read + Seek> Iterator for KlvReader<R> {
+    type Item = io::Result<Klv>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // After an error the position can't be trusted, so stop there
+        if self.failed {
+            return None;
+        }
+        match self.read_klv() {
+            Ok(Some(klv)) => Some(Ok(klv)),
+            Ok(None) => None,
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+// Decode a BER length, returns the length and the number of bytes it used
+pub fn read_ber_length<R: Read>(reader: &mut R) -> io::Result<(u64, u64)> {
+    let mut first = [0u8; 1];
+    reader.read_exact(&mut first)?;
+    if first[0] < 0x80 {
+        // Short form, the length fits in the first byte
+        return Ok((first[0] as u64, 1));
+    }
+
+    let count = (first[0] & 0x7f) as usize;
+    if count == 0 || count > 8 {
+        return Err(invalid_data(format!("unsupported BER length of {} bytes", count)));
+    }
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes[..count])?;
+    let length = bytes[..count].iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+    Ok((length, 1 + count as u64))
+}
+
+// Format a label the way MXFDump prints it (06.0e.2b.34...)
+pub fn format_ul(ul: &UL) -> String {
+    ul.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+// Human readable name of the well known keys, as printed by MXFDump
+pub fn key_name(key: &UL) -> &'static str {
+    if key[0..4] != UL_PREFIX {
+        return "Unknown";
+    }
+    match (key[4], key[5]) {
+        (0x02, 0x05) if key[8..13] == [0x0d, 0x01, 0x02, 0x01, 0x01] => match (key[13], key[14]) {
+            (0x02, 0x01) => "OpenIncompleteHeader",
+            (0x02, 0x02) => "ClosedIncompleteHeader",
+            (0x02, 0x03) => "OpenCompleteHeader",
+            (0x02, 0x04) => "ClosedCompleteHeader",
+            (0x03, 0x01) => "OpenIncompleteBody",
+            (0x03, 0x02) => "ClosedIncompleteBody",
+            (0x03, 0x03) => "OpenCompleteBody",
+            (0x03, 0x04) => "ClosedCompleteBody",
+            (0x04, 0x02) => "IncompleteFooter",
+            (0x04, 0x04) => "Footer",
+            (0x05, 0x01) => "Primer",
+            (0x11, 0x01) => "RandomIndexMetadata",
+            _ => "Unknown",
+        },
+        (0x02, 0x53) if key[13] == 0x10 => "IndexTableSegment",
+        (0x02, 0x53) => "LocalSet",
+        (0x01, 0x01) if key[8..12] == [0x03, 0x01, 0x02, 0x10] => "KLVFill",
+        (0x01, 0x02) => "Essence Element",
+        _ => "Unknown",
+    }
+}
+
+// Like read_exact, but returns how many bytes were read before end of file
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const HEADER_KEY: UL = [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x05, 0x01, 0x01, 0x0d, 0x01, 0x02, 0x01, 0x01, 0x02, 0x04, 0x00];
+
+    #[test]
+    fn short_and_long_form_ber_lengths() {
+        assert_eq!(read_ber_length(&mut &[0x05][..]).unwrap(), (5, 1));
+        assert_eq!(read_ber_length(&mut &[0x83, 0x01, 0x00, 0x00][..]).unwrap(), (0x10000, 4));
+        assert_eq!(read_ber_length(&mut &[0x88, 0, 0, 0, 0, 0, 0, 0x01, 0x02][..]).unwrap(), (0x102, 9));
+        // Indefinite and longer than 8 bytes aren't used by MXF
+        assert!(read_ber_length(&mut &[0x80][..]).is_err());
+        assert!(read_ber_length(&mut &[0x89, 0, 0, 0, 0, 0, 0, 0, 0, 1][..]).is_err());
+        assert!(read_ber_length(&mut &[0x82, 0x01][..]).is_err());
+    }
+
+    #[test]
+    fn packets_are_walked_and_read() {
+        let mut file = [HEADER_KEY.to_vec(), vec![0x03, 1, 2, 3]].concat();
+        file.extend(HEADER_KEY);
+        file.extend([0x83, 0, 0, 2, 4, 5]);
+        let mut reader = KlvReader::new(Cursor::new(file));
+        let first = reader.next().unwrap().unwrap();
+        assert_eq!(first, Klv { key: HEADER_KEY, length: 3, offset: 0, value_offset: 17 });
+        let second = reader.next().unwrap().unwrap();
+        assert_eq!((second.offset, second.value_offset, second.length_size()), (20, 40, 4));
+        assert!(reader.next().is_none());
+        assert_eq!(reader.read_value(&first).unwrap(), [1, 2, 3]);
+        assert_eq!(reader.read_value(&second).unwrap(), [4, 5]);
+    }
+
+    #[test]
+    fn truncated_key_is_an_error() {
+        let mut reader = KlvReader::new(Cursor::new(HEADER_KEY[..9].to_vec()));
+        assert_eq!(reader.next().unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn value_past_the_end_of_the_file_is_not_allocated() {
+        let file = [HEADER_KEY.to_vec(), vec![0x88, 0, 0, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff], vec![0; 100]].concat();
+        let mut reader = KlvReader::new(Cursor::new(file));
+        let klv = reader.next().unwrap().unwrap();
+        assert_eq!(reader.read_value(&klv).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,224 +1,171 @@
-use std::env;
-use std::process::{Command, Stdio};
-use std::io::{self, Read, BufReader};
-use regex::Regex;
-use sled::{Config,Db};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process::ExitCode;
+use std::time::Duration;
+use clap::{ArgMatches, ErrorKind};
+use exit::Outcome;
+use output::Output;
 
+mod analyze;
+mod cli;
+mod dump;
+mod dumper;
+mod dumptext;
+mod exit;
+mod export;
+mod fingerprint;
+mod fix;
+mod job;
+mod klv;
+mod metadata;
+mod output;
+mod partition;
+mod primer;
+mod record;
+mod report;
+mod rip;
 mod scan;
-mod youkyouk;
+#[cfg(test)]
+mod testmxf;
+mod timecode;
+mod worker;
 
-// Size of chunks to read (8 kB)
-const CHUNK_SIZE: usize = 8192;
-
-fn main() -> io::Result<()> {
-    let mut videofilepath: String = "".to_string();  
-
-    // Get command line arguments, excluding the program name
-    let args: Vec<String> = env::args().collect();
-    let videofolderpath = &args[1];
-	let mut verbose = false;
-	let mut mxferror = false;
-	
-	let mut i = 2;
-	while i < args.len() {
-		match args[i].as_str() {
-			"-v" | "--verbose" => {
-				verbose = true;
-				i+=1;
-			}
-			"-e" | "--errors" => {
-				mxferror = true;
-				i+=1;
-			}
-			_ => {
-				i+=1;
-			}
-		}
-	}
-			
-    println!("Running the folder scan, for MXF files...");
-    scan::scandir(videofolderpath, verbose);
+fn main() -> ExitCode {
+    let matches = match cli::build_cli().get_matches_safe() {
+        Ok(matches) => matches,
+        // Help and version go to stdout and exit successfully
+        Err(e) if matches!(e.kind, ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed) => e.exit(),
+        Err(e) => {
+            eprintln!("{}", e.message);
+            return ExitCode::from(exit::USAGE);
+        }
+    };
 
-    // Initialize the sled database
-    let db = Config::new()
-        .path("./file_paths_db")
-        .open()
-        .unwrap();
+    let result = match matches.subcommand() {
+        ("scan", Some(args)) => run_scan(args),
+        ("analyze", Some(args)) => run_analyze(args),
+        ("analyze-dump", Some(args)) => run_analyze_dump(args),
+        ("report", Some(args)) => report::run_report(args),
+        ("query", Some(args)) => report::run_query(args),
+        ("fix", Some(args)) => fix::run_fix(args),
+        _ => unreachable!("clap requires a subcommand"),
+    };
+    match result {
+        Ok(outcome) => outcome.code(),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Outcome::Error.code()
+        }
+    }
+}
 
-    // Create command to execute mxfdump.exe
-    let mut cmd = Command::new("./bin/mxfdump.exe");
+// Add the MXF files of the given folders to the database
+fn run_scan(args: &ArgMatches) -> io::Result<Outcome> {
+    let verbose = args.is_present("verbose");
+    // Also fingerprint the file content, slower but catches copies keeping size and mtime
+    let with_hash = args.is_present("hash");
+    // Remove the entries of missing files instead of keeping tombstones
+    let prune = args.is_present("prune");
 
-    println!("\nIterating over all entries in DB...");
-    println!("Running mxfdump.exe with provided arguments...");
-    for result in db.iter() {
-        match result {
-            Ok((key_bytes, _value_bytes)) => {
-                if let Ok(videofilepathb64) = String::from_utf8(key_bytes.to_vec()) {
-                    let decodedvideofilepath = decodeb64(&videofilepathb64);
-                    match decodedvideofilepath {
-                        Ok(val) => {
-                            videofilepath = val
-                        }
-                        Err(e) => eprintln!("Error decoding {} : {}", &videofilepathb64, e)
-                    }
-                    if verbose {
-                        println!("This is the path I got: {}",&videofilepath);
-                    }
-                    // Configure to capture stdout and stderr
-                    let mut child = cmd
-            	    	.arg(&videofilepath)
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped())
-                        .spawn()?;
-                    
-                    // Extract stdout and stderr handles
-                    let stdout = child.stdout.take().expect("Failed to capture stdout");
-                    let stderr = child.stderr.take().expect("Failed to capture stderr");
-                    
-                    // Prepare regex pattern
-                    let pattern = r"\[ k = Origin\s+\r?\n?4b\.02, l =\s+\d+\s+\(\d+\) \]\s+\r?\n?\s+\d+\s+([0-9a-fA-F]{2}(?: [0-9a-fA-F]{2}){7})";
-                    let regex = match Regex::new(pattern) {
-                        Ok(re) => re,
-                        Err(e) => {
-                            eprintln!("Regex error: {}", e);
-                            return Ok(());
-                        }
-                    };
-                    
-                    println!("\n--- PROCESSING MXFDump output ---");
-                    // Process stdout in chunks, piping to standard output
-                    let mut stdout_matches = Vec::new();
-                    process_output(BufReader::new(stdout), true, &regex, &mut stdout_matches, &videofilepath, verbose, &db)?;
-            	    
-                    if mxferror {
-            	    	println!("\n--- PROCESSING MXFDump Error output ---");
-            	    	// Process stderr in chunks, piping to standard error
-            	    	let mut stderr_matches = Vec::new();
-            	    	process_output(BufReader::new(stderr), false, &regex, &mut stderr_matches, &videofilepath, verbose, &db)?;
-            	    }
-                    
-                    // Wait for the process to complete
-                    let status = child.wait()?;
-                    if verbose {
-                        println!("\nExit status: {}", status);
-                    }
-                    
-                    // Print regex matches
-                    println!("\n--- Looking for Origin/Precharge ---");
-                    if stdout_matches.is_empty() {
-                        println!("No matches found for the Origin,Precharge pattern.\n");
-                    } else {
-                        let total_matches = stdout_matches.len();
-                        println!("Found {} match(es) for Origin, Precharge pattern.\n", total_matches);
-                        
-                        if verbose {
-                            if !stdout_matches.is_empty() {
-                                println!("\nMatches from MXFDump output:");
-                                for (idx, m) in stdout_matches.iter().enumerate() {
-                                    println!("Match #{}: {}", idx + 1, m);
-                                }
-                            }
-                        }
-                    }
-                    //println!("my key {videofilepath}")
-                } else {
-                    eprintln!("couldn't decode the key");
-                }
-            }
-            Err(e) => {
-                eprintln!("Error during iteration {e}");
-            }
+    let db = record::open_db()?;
+    let mut roots = Vec::new();
+    for videofolderpath in args.values_of("dirs").into_iter().flatten() {
+        println!("Running the folder scan of {}, for MXF files...", videofolderpath);
+        scan::scandir(&db, videofolderpath, verbose, with_hash)?;
+        roots.push(scan::root(videofolderpath));
+    }
+    // Files moved or deleted since the last run shouldn't be analysed again
+    let pruned = scan::reconcile(&db, &roots, prune, verbose)?;
+    if !pruned.is_empty() {
+        println!("{} {} missing MXF file(s):", if prune { "Removed" } else { "Tombstoned" }, pruned.len());
+        for file_path in &pruned {
+            println!("  {}", file_path);
         }
     }
-    
-    Ok(())
+
+    Ok(Outcome::Clean)
 }
 
-// Process output stream in chunks, collect regex matches
-fn process_output<R: Read>(
-    mut reader: BufReader<R>, 
-    is_stdout: bool, 
-    regex: &Regex, 
-    matches: &mut Vec<String>,
-	videofilepath: &String,
-	verbose: bool,
-    db: &Db
-) -> io::Result<()> {
-    // For regex matching across chunk boundaries
-    let mut leftover = String::new();
-    let mut buffer = [0; CHUNK_SIZE];
-    
-	println!("Processing {}", &videofilepath);
-    // Read and process in chunks
-    loop {
-        let bytes_read = reader.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break; // End of stream
-        }
-        
-        // Convert chunk to string, handling UTF-8 errors gracefully
-        let chunk = match std::str::from_utf8(&buffer[0..bytes_read]) {
-            Ok(s) => s,
-            Err(_) => {
-                // Handle invalid UTF-8 (just output the bytes we can)
-                let string = String::from_utf8_lossy(&buffer[0..bytes_read]).to_string();
-                if is_stdout {
-                    print!("{}", string);
-                } else {
-                    eprint!("{}", string);
-                }
-                continue;
-            }
-        };
-        
-        // Print current chunk
-        if is_stdout {
-			if verbose {
-				print!("{}", chunk);
-			}
-        } else {
-            eprint!("{}", chunk);
-        }
-        
-        // Combine leftover with current chunk for regex matching
-        leftover.push_str(chunk);
-        
-        // Extract regex matches from the combined text
-        for cap in regex.captures_iter(&leftover) {
-            if let Some(hex_value) = cap.get(1) {
-                //maybe we want to stop at first match by default
-                matches.push(hex_value.as_str().to_string());
-                //Set the value of the file path to true ( we have found Origin/Precharge in the MXF )
-                let originpresent = true;
-                let videofilepathb64 = base64::encode(videofilepath.as_bytes());
-                let _ = db.insert(&videofilepathb64.as_bytes(), originpresent.to_string().as_bytes());
+// Analyse the new and modified files of the database, the outcome covers
+// every file of the database, including the unchanged ones analysed before
+fn run_analyze(args: &ArgMatches) -> io::Result<Outcome> {
+    let options = dumper::Options {
+        verbose: args.is_present("verbose"),
+        mxferror: args.is_present("errors"),
+        // Stop after the header metadata instead of walking the essence
+        header_only: args.is_present("header-only"),
+        // Seconds, validated by clap
+        timeout: args.value_of("timeout").and_then(|timeout| timeout.parse().ok()).map(Duration::from_secs),
+    };
+    let verbose = options.verbose;
+    // Reanalyse every file, even the unchanged ones
+    let force = args.is_present("force");
+    let policy = cli::origin_policy(args);
+    // Number of files analysed at once, validated by clap
+    let jobs = args.value_of("jobs").and_then(|jobs| jobs.parse().ok()).unwrap_or(1);
+    // Native parser or external dump tool, from the flags or their environment variables
+    let backend = args.value_of("dumper").unwrap_or("native");
+    let dumper = dumper::select(backend, args.value_of("dumper-path"), args.value_of("dumper-command"))?;
+    // The stub serves the same canned dump for every file, a dry run that mustn't reach the records
+    let dry_run = backend == "stub";
+
+    let db = record::open_db()?;
+
+    // Collect the files to analyse first, so that workers can share the list
+    println!("\nIterating over all entries in DB...");
+    let (todo, skipped) = job::pending(&db, force, verbose);
+
+    println!("Analysing {} MXF file(s) with {} job(s) using {}...", todo.len(), jobs, dumper.name());
+    let mut outcome = Outcome::Clean;
+    worker::run_ordered(
+        todo,
+        jobs,
+        |job| {
+            let mut out = Output::default();
+            let filerecord = job.run(dumper.as_ref(), &options, policy, &mut out);
+            // sled handles concurrent writers, each worker saves its own result
+            if !dry_run && let Err(e) = db.insert(job.key.as_bytes(), filerecord.encode()) {
+                out.error(format!("Error saving {} : {}", job.path, e));
             }
-        }
-        
-        // Keep the last few bytes for the next iteration
-        // This helps with matches that might span chunks
-        if leftover.len() > 80 {
-            leftover = leftover.chars().skip(leftover.len() - 80).collect();
-        }
-    }
+            (out, Outcome::of(filerecord.status))
+        },
+        |(out, file_outcome)| {
+            out.print();
+            outcome = outcome.max(file_outcome);
+        },
+    );
 
-    Ok(())
+    if dry_run {
+        println!("\nThe stub results were not saved, the database is unchanged.");
+        return Ok(outcome);
+    }
+    println!("\nSkipped {} unchanged file(s) already analysed.", skipped);
+    report::print_summary(&db);
+    Ok(report::outcome(&db))
 }
 
-fn decodeb64(file_path_b64: &String) -> Result<String,String> {
-    match base64::decode(&file_path_b64) {
-        Ok(decoded_bytes) => {
-            match String::from_utf8(decoded_bytes){
-                Ok(decoded_string) => Ok(decoded_string),
-                Err(err) => {
-                    eprintln!("Couldn't decode the base64 value as UTF-8 {err}");
-                    Err("Invalid UTF-8".to_string())
+// Analyse saved MXFDump text, the database is left untouched
+fn run_analyze_dump(args: &ArgMatches) -> io::Result<Outcome> {
+    let verbose = args.is_present("verbose");
+    let policy = cli::origin_policy(args);
+
+    let mut outcome = Outcome::Clean;
+    for dumpfilepath in args.values_of("files").into_iter().flatten() {
+        let mut out = Output::default();
+        out.line(format!("\n--- PROCESSING MXFDump text {} ---", dumpfilepath));
+        match File::open(dumpfilepath).and_then(|file| dump::read_dump(BufReader::new(file), verbose, &mut out)) {
+            Ok(analysis) => {
+                job::print_analysis(dumpfilepath, &analysis, policy, verbose, &mut out);
+                if analysis.has_origin(policy) {
+                    outcome = outcome.max(Outcome::Origin);
                 }
             }
+            Err(e) => {
+                out.error(format!("Error reading {} : {}", dumpfilepath, e));
+                outcome = Outcome::Error;
+            }
         }
-        Err(err) => {
-            eprintln!("Couldn't decode the base64 value {err}");
-            Err("Invalid UTF-8".to_string())
-        }
+        out.print();
     }
+    Ok(outcome)
 }
@@ -0,0 +1,392 @@
+//Header metadata object graph: Preface -> ContentStorage -> Packages -> Tracks -> Sequence -> components
+use std::collections::HashMap;
+use crate::klv::{self, UL};
+use crate::partition::{PartitionKind, PartitionPack};
+use crate::primer::LocalSet;
+use crate::timecode::Rational;
+
+// Property labels used to follow the strong references (SMPTE 377)
+pub const INSTANCE_UID: UL = ul(&[0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x15, 0x02]);
+pub const CONTENT_STORAGE: UL = ul(&[0x01, 0x01, 0x01, 0x02, 0x06, 0x01, 0x01, 0x04, 0x02, 0x01]);
+pub const PACKAGES: UL = ul(&[0x01, 0x01, 0x01, 0x02, 0x06, 0x01, 0x01, 0x04, 0x05, 0x01]);
+pub const PACKAGE_UID: UL = ul(&[0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x15, 0x10]);
+pub const PACKAGE_NAME: UL = ul(&[0x01, 0x01, 0x01, 0x01, 0x01, 0x03, 0x03, 0x02, 0x01]);
+pub const TRACKS: UL = ul(&[0x01, 0x01, 0x01, 0x02, 0x06, 0x01, 0x01, 0x04, 0x06, 0x05]);
+pub const TRACK_ID: UL = ul(&[0x01, 0x01, 0x01, 0x02, 0x01, 0x07, 0x01, 0x01]);
+pub const TRACK_NUMBER: UL = ul(&[0x01, 0x01, 0x01, 0x02, 0x01, 0x04, 0x01, 0x03]);
+pub const TRACK_NAME: UL = ul(&[0x01, 0x01, 0x01, 0x02, 0x01, 0x07, 0x01, 0x02, 0x01]);
+pub const SEQUENCE: UL = ul(&[0x01, 0x01, 0x01, 0x02, 0x06, 0x01, 0x01, 0x04, 0x02, 0x04]);
+pub const STRUCTURAL_COMPONENTS: UL = ul(&[0x01, 0x01, 0x01, 0x02, 0x06, 0x01, 0x01, 0x04, 0x06, 0x09]);
+pub const EDIT_RATE: UL = ul(&[0x01, 0x01, 0x01, 0x02, 0x05, 0x30, 0x04, 0x05]);
+const ORIGIN: UL = crate::primer::ORIGIN_UL;
+
+// Build a property label from the bytes following the 06.0e.2b.34 prefix
+const fn ul(rest: &[u8]) -> UL {
+    let mut label = [0u8; 16];
+    label[0] = 0x06;
+    label[1] = 0x0e;
+    label[2] = 0x2b;
+    label[3] = 0x34;
+    let mut i = 0;
+    while i < rest.len() {
+        label[4 + i] = rest[i];
+        i += 1;
+    }
+    label
+}
+
+// Name of a header metadata set, as printed by MXFDump
+pub fn set_name(key: &UL) -> &'static str {
+    if key[4..6] != [0x02, 0x53] || key[8..14] != [0x0d, 0x01, 0x01, 0x01, 0x01, 0x01] {
+        return klv::key_name(key);
+    }
+    match key[14] {
+        0x2f => "MXFPreface",
+        0x30 => "MXFIdentification",
+        0x18 => "MXFContentStorage",
+        0x23 => "MXFEssenceContainerData",
+        0x34 => "MXFGenericPackage",
+        0x36 => "MXFMaterialPackage",
+        0x37 => "MXFSourcePackage",
+        0x38 => "MXFGenericTrack",
+        0x39 => "MXFEventTrack",
+        0x3a => "MXFStaticTrack",
+        0x3b => "MXFTrack",
+        0x0f => "MXFSequence",
+        0x11 => "MXFSourceClip",
+        0x14 => "MXFTimecodeComponent",
+        0x41 => "MXFDMSegment",
+        0x44 => "MXFMultipleDescriptor",
+        0x47 => "MXFAES3AudioDescriptor",
+        0x48 => "MXFWaveAudioDescriptor",
+        0x42 => "MXFGenericSoundDescriptor",
+        0x27 => "MXFGenericPictureDescriptor",
+        0x28 => "MXFCDCIDescriptor",
+        0x29 => "MXFRGBADescriptor",
+        0x51 => "MXFMPEG2VideoDescriptor",
+        0x5a => "MXFJPEG2000PictureSubDescriptor",
+        _ => "MXFLocalSet",
+    }
+}
+
+// The header metadata sets of one partition, indexed by InstanceUID
+#[derive(Debug)]
+pub struct HeaderMetadata {
+    // Partition the sets were read from
+    pub partition: PartitionKind,
+    pub partition_offset: u64,
+    // Status of that partition, closed and complete metadata is final
+    pub closed: bool,
+    pub complete: bool,
+    sets: Vec<LocalSet>,
+    by_uid: HashMap<UL, usize>,
+}
+
+// Position type of SMPTE 377, a signed count of edit units
+pub type Position = i64;
+
+// Material packages describe the playout timeline, source (and file) packages the stored essence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageKind {
+    Material,
+    Source,
+    Other,
+}
+
+impl PackageKind {
+    pub fn of(key: &UL) -> PackageKind {
+        match set_name(key) {
+            "MXFMaterialPackage" => PackageKind::Material,
+            "MXFSourcePackage" => PackageKind::Source,
+            _ => PackageKind::Other,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PackageKind::Material => "Material Package",
+            PackageKind::Source => "File/Source Package",
+            PackageKind::Other => "Package",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub kind: PackageKind,
+    pub set_name: &'static str,
+    pub offset: u64,
+    pub uid: Option<Vec<u8>>,
+    pub name: Option<String>,
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub set_name: &'static str,
+    pub offset: u64,
+    pub track_id: Option<u32>,
+    pub track_number: Option<u32>,
+    pub name: Option<String>,
+    pub edit_rate: Option<Rational>,
+    pub origin: Option<Position>,
+    // Absolute file offset of the 8-byte Origin value
+    pub origin_offset: Option<u64>,
+    pub sequence: Option<Sequence>,
+}
+
+impl Package {
+    // Video (or timecode) rate of the package, used to show audio origins as timecode
+    pub fn frame_rate(&self) -> Option<Rational> {
+        self.tracks
+            .iter()
+            .filter_map(|track| track.edit_rate)
+            .find(|rate| !rate.is_audio())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sequence {
+    pub offset: u64,
+    // Set names of the structural components, in order
+    pub components: Vec<&'static str>,
+}
+
+impl HeaderMetadata {
+    pub fn new(partition: &PartitionPack, partition_offset: u64) -> HeaderMetadata {
+        HeaderMetadata {
+            partition: partition.kind,
+            partition_offset,
+            closed: partition.closed,
+            complete: partition.complete,
+            sets: Vec::new(),
+            by_uid: HashMap::new(),
+        }
+    }
+
+    // Rank of this copy among the others of the file, higher is more authoritative:
+    // closed and complete first, then closed, then complete, a later copy wins a tie
+    pub fn authority(&self) -> (bool, bool, u64) {
+        (self.closed, self.complete, self.partition_offset)
+    }
+
+    pub fn add(&mut self, set: LocalSet) {
+        if let Some(uid) = set.find(&INSTANCE_UID).and_then(|item| to_uid(&item.value)) {
+            self.by_uid.insert(uid, self.sets.len());
+        }
+        self.sets.push(set);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    // Resolve a strong reference
+    fn get(&self, uid: &UL) -> Option<&LocalSet> {
+        self.by_uid.get(uid).map(|&index| &self.sets[index])
+    }
+
+    fn preface(&self) -> Option<&LocalSet> {
+        self.sets.iter().find(|set| set_name(&set.key) == "MXFPreface")
+    }
+
+    // Follow the strong references from the Preface down to the track components
+    pub fn packages(&self) -> Result<Vec<Package>, String> {
+        let preface = self.preface().ok_or("no Preface set in the header metadata")?;
+        let storage_uid = reference(preface, &CONTENT_STORAGE).ok_or("Preface has no ContentStorage")?;
+        let storage = self.get(&storage_uid)
+            .ok_or_else(|| format!("ContentStorage {} not found", format_uid(&storage_uid)))?;
+
+        let mut packages = Vec::new();
+        for package_uid in references(storage, &PACKAGES) {
+            let Some(package) = self.get(&package_uid) else {
+                return Err(format!("Package {} not found", format_uid(&package_uid)));
+            };
+            let mut tracks = Vec::new();
+            for track_uid in references(package, &TRACKS) {
+                let Some(track) = self.get(&track_uid) else {
+                    return Err(format!("Track {} not found", format_uid(&track_uid)));
+                };
+                tracks.push(self.track(track));
+            }
+            packages.push(Package {
+                kind: PackageKind::of(&package.key),
+                set_name: set_name(&package.key),
+                offset: package.offset,
+                uid: package.find(&PACKAGE_UID).map(|item| item.value.clone()),
+                name: package.find(&PACKAGE_NAME).map(|item| utf16_string(&item.value)),
+                tracks,
+            });
+        }
+        Ok(packages)
+    }
+
+    fn track(&self, track: &LocalSet) -> Track {
+        let sequence = reference(track, &SEQUENCE)
+            .and_then(|uid| self.get(&uid))
+            .map(|sequence| Sequence {
+                offset: sequence.offset,
+                components: references(sequence, &STRUCTURAL_COMPONENTS)
+                    .iter()
+                    .filter_map(|uid| self.get(uid))
+                    .map(|component| set_name(&component.key))
+                    .collect(),
+            });
+        let origin = track.find(&ORIGIN).and_then(|item| Some((to_position(&item.value)?, item.value_offset)));
+        Track {
+            set_name: set_name(&track.key),
+            offset: track.offset,
+            track_id: track.find(&TRACK_ID).and_then(|item| to_u32(&item.value)),
+            track_number: track.find(&TRACK_NUMBER).and_then(|item| to_u32(&item.value)),
+            name: track.find(&TRACK_NAME).map(|item| utf16_string(&item.value)),
+            edit_rate: track.find(&EDIT_RATE).and_then(|item| Rational::from_bytes(&item.value)),
+            origin: origin.map(|(origin, _)| origin),
+            origin_offset: origin.map(|(_, offset)| offset),
+            sequence,
+        }
+    }
+}
+
+// Value of a single strong reference property
+fn reference(set: &LocalSet, property: &UL) -> Option<UL> {
+    set.find(property).and_then(|item| to_uid(&item.value))
+}
+
+// Values of a strong reference batch property (count, item size, items)
+fn references(set: &LocalSet, property: &UL) -> Vec<UL> {
+    let Some(item) = set.find(property) else {
+        return Vec::new();
+    };
+    let value = &item.value;
+    if value.len() < 8 {
+        return Vec::new();
+    }
+    let count = u32::from_be_bytes([value[0], value[1], value[2], value[3]]) as usize;
+    let size = u32::from_be_bytes([value[4], value[5], value[6], value[7]]) as usize;
+    if size != 16 {
+        return Vec::new();
+    }
+    value[8..]
+        .chunks_exact(16)
+        .take(count)
+        .filter_map(to_uid)
+        .collect()
+}
+
+fn to_uid(value: &[u8]) -> Option<UL> {
+    value.try_into().ok()
+}
+
+// Positions are stored as 8-byte big endian signed integers
+fn to_position(value: &[u8]) -> Option<Position> {
+    value.try_into().ok().map(i64::from_be_bytes)
+}
+
+fn to_u32(value: &[u8]) -> Option<u32> {
+    value.try_into().ok().map(u32::from_be_bytes)
+}
+
+// MXF strings are UTF-16 big endian, sometimes null terminated
+fn utf16_string(value: &[u8]) -> String {
+    let units: Vec<u16> = value
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+pub fn format_uid(uid: &[u8]) -> String {
+    uid.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::klv::Klv;
+    use crate::primer::Primer;
+    use crate::testmxf::{self, uid};
+
+    // Sets laid out one after the other from 0x100, each with a 4-byte BER length
+    fn header_metadata(sets: &[(UL, Vec<u8>)]) -> HeaderMetadata {
+        let primer = Primer::parse(&testmxf::primer()).unwrap();
+        let mut metadata = HeaderMetadata::new(&testmxf::partition_pack(testmxf::HEADER, true, true), 0);
+        let mut offset = 0x100;
+        for (key, value) in sets {
+            let klv = Klv { key: *key, length: value.len() as u64, offset, value_offset: offset + 20 };
+            metadata.add(LocalSet::parse(&klv, value, &primer).unwrap());
+            offset = klv.value_offset + klv.length;
+        }
+        metadata
+    }
+
+    // Generated sets without the one of the given InstanceUID
+    fn sets_without(uid: UL) -> Vec<(UL, Vec<u8>)> {
+        let primer = Primer::parse(&testmxf::primer()).unwrap();
+        testmxf::sets(16, 0)
+            .into_iter()
+            .filter(|(key, value)| {
+                let klv = Klv { key: *key, length: value.len() as u64, offset: 0, value_offset: 20 };
+                let set = LocalSet::parse(&klv, value, &primer).unwrap();
+                set.find(&INSTANCE_UID).is_none_or(|item| item.value != uid)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn packages_are_resolved_from_the_preface() {
+        let metadata = header_metadata(&testmxf::sets(16, -2));
+        let packages = metadata.packages().unwrap();
+        let kinds: Vec<PackageKind> = packages.iter().map(|package| package.kind).collect();
+        assert_eq!(kinds, [PackageKind::Material, PackageKind::Source]);
+
+        let track = &packages[0].tracks[0];
+        assert_eq!((track.set_name, track.track_id, track.origin), ("MXFTrack", Some(1), Some(16)));
+        assert_eq!(track.edit_rate, Some(Rational { numerator: 25, denominator: 1 }));
+        // Past the InstanceUID, TrackID and EditRate items, and the Origin tag and length
+        assert_eq!(track.origin_offset, Some(track.offset + 20 + 20 + 8 + 12 + 4));
+        assert_eq!(track.sequence.as_ref().unwrap().components, ["MXFSourceClip"]);
+        assert_eq!(packages[1].tracks[0].origin, Some(-2));
+    }
+
+    #[test]
+    fn dangling_strong_references_are_errors() {
+        let error = |uid: UL| header_metadata(&sets_without(uid)).packages().unwrap_err();
+        assert_eq!(error(uid(1)), "no Preface set in the header metadata");
+        assert_eq!(error(uid(2)), format!("ContentStorage {} not found", format_uid(&uid(2))));
+        assert_eq!(error(uid(4)), format!("Package {} not found", format_uid(&uid(4))));
+        assert_eq!(error(uid(0x30)), format!("Track {} not found", format_uid(&uid(0x30))));
+        // A missing sequence only leaves the track without one
+        let packages = header_metadata(&sets_without(uid(0x31))).packages().unwrap();
+        assert!(packages[0].tracks[0].sequence.is_none());
+    }
+
+    #[test]
+    fn reference_batches_are_decoded() {
+        let primer = Primer::parse(&testmxf::primer()).unwrap();
+        let storage = |value: &[u8]| {
+            let value = testmxf::item(0x1901, value);
+            let klv = Klv { key: testmxf::set_key(0x18), length: value.len() as u64, offset: 0, value_offset: 20 };
+            references(&LocalSet::parse(&klv, &value, &primer).unwrap(), &PACKAGES)
+        };
+        assert_eq!(storage(&testmxf::batch(&[uid(3), uid(4)])), [uid(3), uid(4)]);
+        // The count wins over extra items, and the items over a larger count
+        let mut batch = testmxf::batch(&[uid(3), uid(4)]);
+        batch[3] = 1;
+        assert_eq!(storage(&batch), [uid(3)]);
+        batch[3] = 3;
+        assert_eq!(storage(&batch), [uid(3), uid(4)]);
+        // Only 16-byte items are references
+        batch[7] = 8;
+        assert!(storage(&batch).is_empty());
+        assert!(storage(&batch[..6]).is_empty());
+    }
+
+    #[test]
+    fn package_kind_comes_from_the_set_key() {
+        assert_eq!(PackageKind::of(&testmxf::set_key(0x36)), PackageKind::Material);
+        assert_eq!(PackageKind::of(&testmxf::set_key(0x37)), PackageKind::Source);
+        assert_eq!(PackageKind::of(&testmxf::set_key(0x34)), PackageKind::Other);
+        assert_eq!(PackageKind::of(&testmxf::set_key(0x3b)), PackageKind::Other);
+    }
+}
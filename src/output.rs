@@ -0,0 +1,34 @@
+//Console output of one job, buffered so that parallel jobs print in a readable order
+
+#[derive(Debug, Default)]
+pub struct Output {
+    // (is_error, line) in the order they were written
+    lines: Vec<(bool, String)>,
+}
+
+impl Output {
+    // A line for standard output
+    pub fn line(&mut self, line: String) {
+        self.lines.push((false, line));
+    }
+
+    // A line for standard error
+    pub fn error(&mut self, line: String) {
+        self.lines.push((true, line));
+    }
+
+    // Add the lines of another output after these ones
+    pub fn append(&mut self, other: Output) {
+        self.lines.extend(other.lines);
+    }
+
+    pub fn print(self) {
+        for (is_error, line) in self.lines {
+            if is_error {
+                eprintln!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+        }
+    }
+}
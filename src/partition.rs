@@ -0,0 +1,222 @@
+//Partition pack decoding (header, body and footer partitions)
+use crate::klv::{self, UL};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionKind {
+    Header,
+    Body,
+    Footer,
+}
+
+impl PartitionKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PartitionKind::Header => "Header",
+            PartitionKind::Body => "Body",
+            PartitionKind::Footer => "Footer",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionPack {
+    pub kind: PartitionKind,
+    // An open partition may still be modified by the writer
+    pub closed: bool,
+    // An incomplete partition may be missing some header metadata values
+    pub complete: bool,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub kag_size: u32,
+    pub this_partition: u64,
+    pub previous_partition: u64,
+    pub footer_partition: u64,
+    pub header_byte_count: u64,
+    pub index_byte_count: u64,
+    pub index_sid: u32,
+    pub body_offset: u64,
+    pub body_sid: u32,
+    pub operational_pattern: UL,
+    pub essence_containers: Vec<UL>,
+}
+
+// Fixed part of a partition pack, before the essence container batch
+const FIXED_SIZE: usize = 80;
+
+// Tell if a key is a partition pack key, and which one
+pub fn partition_kind(key: &UL) -> Option<(PartitionKind, bool, bool)> {
+    if key[0..4] != klv::UL_PREFIX || key[4..6] != [0x02, 0x05] || key[8..13] != [0x0d, 0x01, 0x02, 0x01, 0x01] {
+        return None;
+    }
+    let kind = match key[13] {
+        0x02 => PartitionKind::Header,
+        0x03 => PartitionKind::Body,
+        0x04 => PartitionKind::Footer,
+        _ => return None,
+    };
+    // Status byte: 01 open incomplete, 02 closed incomplete, 03 open complete, 04 closed complete
+    let (closed, complete) = match key[14] {
+        0x01 => (false, false),
+        0x02 => (true, false),
+        0x03 => (false, true),
+        0x04 => (true, true),
+        _ => return None,
+    };
+    // Footers are never open
+    if kind == PartitionKind::Footer && !closed {
+        return None;
+    }
+    Some((kind, closed, complete))
+}
+
+impl PartitionPack {
+    // Decode the value of a partition pack KLV
+    pub fn parse(key: &UL, value: &[u8]) -> Result<PartitionPack, String> {
+        let (kind, closed, complete) = partition_kind(key)
+            .ok_or_else(|| format!("{} is not a partition pack key", klv::format_ul(key)))?;
+        if value.len() < FIXED_SIZE + 8 {
+            return Err(format!("partition pack too short ({} bytes)", value.len()));
+        }
+
+        let mut operational_pattern = [0u8; 16];
+        operational_pattern.copy_from_slice(&value[64..80]);
+
+        // Essence containers are stored as a batch: count, item size, items
+        let count = be_u32(value, FIXED_SIZE) as usize;
+        let item_size = be_u32(value, FIXED_SIZE + 4) as usize;
+        if item_size == 16 && count > (value.len() - FIXED_SIZE - 8) / 16 {
+            return Err(format!("essence container batch of {} items doesn't fit in {} bytes", count, value.len()));
+        }
+        let mut essence_containers = Vec::with_capacity(count);
+        if item_size == 16 {
+            for i in 0..count {
+                let start = FIXED_SIZE + 8 + i * 16;
+                if start + 16 > value.len() {
+                    return Err(format!("essence container batch truncated after {} items", i));
+                }
+                let mut ul = [0u8; 16];
+                ul.copy_from_slice(&value[start..start + 16]);
+                essence_containers.push(ul);
+            }
+        } else if count > 0 {
+            return Err(format!("unexpected essence container item size {}", item_size));
+        }
+
+        Ok(PartitionPack {
+            kind,
+            closed,
+            complete,
+            major_version: be_u16(value, 0),
+            minor_version: be_u16(value, 2),
+            kag_size: be_u32(value, 4),
+            this_partition: be_u64(value, 8),
+            previous_partition: be_u64(value, 16),
+            footer_partition: be_u64(value, 24),
+            header_byte_count: be_u64(value, 32),
+            index_byte_count: be_u64(value, 40),
+            index_sid: be_u32(value, 48),
+            body_offset: be_u64(value, 52),
+            body_sid: be_u32(value, 60),
+            operational_pattern,
+            essence_containers,
+        })
+    }
+
+    // Name as printed by MXFDump, e.g. OpenIncompleteHeader
+    pub fn name(&self) -> String {
+        let kind = self.kind.name();
+        if self.kind == PartitionKind::Footer {
+            return if self.complete { kind.to_string() } else { format!("Incomplete{}", kind) };
+        }
+        format!(
+            "{}{}{}",
+            if self.closed { "Closed" } else { "Open" },
+            if self.complete { "Complete" } else { "Incomplete" },
+            kind
+        )
+    }
+
+    // One line status for the per file report
+    pub fn status(&self) -> String {
+        format!(
+            "{} ({}, {})",
+            self.name(),
+            if self.closed { "closed" } else { "open" },
+            if self.complete { "complete" } else { "incomplete" }
+        )
+    }
+}
+
+// Short name of a generalized operational pattern label (1a to 3c, Atom)
+pub fn operational_pattern_name(ul: &UL) -> String {
+    if ul[0..4] != klv::UL_PREFIX || ul[8..12] != [0x0d, 0x01, 0x02, 0x01] {
+        return klv::format_ul(ul);
+    }
+    match (ul[12], ul[13]) {
+        (0x10, _) => "Atom".to_string(),
+        (item @ 1..=3, package @ 1..=3) => format!("{}{}", item, (b'a' + package - 1) as char),
+        _ => klv::format_ul(ul),
+    }
+}
+
+fn be_u16(value: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([value[at], value[at + 1]])
+}
+
+fn be_u32(value: &[u8], at: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&value[at..at + 4]);
+    u32::from_be_bytes(bytes)
+}
+
+fn be_u64(value: &[u8], at: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&value[at..at + 8]);
+    u64::from_be_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOSED_COMPLETE_HEADER: UL = [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x05, 0x01, 0x01, 0x0d, 0x01, 0x02, 0x01, 0x01, 0x02, 0x04, 0x00];
+
+    // Partition pack value holding one essence container
+    fn pack_value(count: u32) -> Vec<u8> {
+        let mut value = Vec::new();
+        value.extend(1u16.to_be_bytes());
+        value.extend(3u16.to_be_bytes());
+        value.extend(0x200u32.to_be_bytes());
+        value.extend(0u64.to_be_bytes());
+        value.extend(0u64.to_be_bytes());
+        value.extend(0x571429u64.to_be_bytes());
+        value.extend(0x3e00u64.to_be_bytes());
+        value.extend(0u64.to_be_bytes());
+        value.extend(0u32.to_be_bytes());
+        value.extend(0u64.to_be_bytes());
+        value.extend(1u32.to_be_bytes());
+        value.extend([0x06, 0x0e, 0x2b, 0x34, 0x04, 0x01, 0x01, 0x01, 0x0d, 0x01, 0x02, 0x01, 0x01, 0x01, 0x09, 0x00]);
+        value.extend(count.to_be_bytes());
+        value.extend(16u32.to_be_bytes());
+        value.extend([0x06, 0x0e, 0x2b, 0x34, 0x04, 0x01, 0x01, 0x01, 0x0d, 0x01, 0x03, 0x01, 0x02, 0x06, 0x02, 0x00]);
+        value
+    }
+
+    #[test]
+    fn partition_pack_fields_are_decoded() {
+        let partition = PartitionPack::parse(&CLOSED_COMPLETE_HEADER, &pack_value(1)).unwrap();
+        assert_eq!((partition.kind, partition.closed, partition.complete), (PartitionKind::Header, true, true));
+        assert_eq!((partition.major_version, partition.minor_version, partition.kag_size), (1, 3, 0x200));
+        assert_eq!((partition.footer_partition, partition.header_byte_count, partition.body_sid), (0x571429, 0x3e00, 1));
+        assert_eq!(operational_pattern_name(&partition.operational_pattern), "1a");
+        assert_eq!(partition.essence_containers.len(), 1);
+        assert_eq!(partition.name(), "ClosedCompleteHeader");
+    }
+
+    #[test]
+    fn essence_container_count_is_checked_before_allocating() {
+        assert!(PartitionPack::parse(&CLOSED_COMPLETE_HEADER, &pack_value(2)).is_err());
+        assert!(PartitionPack::parse(&CLOSED_COMPLETE_HEADER, &pack_value(u32::MAX)).is_err());
+        assert!(PartitionPack::parse(&CLOSED_COMPLETE_HEADER, &pack_value(1)[..87]).is_err());
+    }
+}
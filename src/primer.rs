@@ -0,0 +1,148 @@
+//Primer pack and local set decoding
+use std::collections::HashMap;
+use crate::klv::{self, UL};
+
+// Origin property of a track (SMPTE 377 Track::Origin)
+pub const ORIGIN_UL: UL = [0x06, 0x0e, 0x2b, 0x34, 0x01, 0x01, 0x01, 0x02, 0x07, 0x02, 0x01, 0x03, 0x01, 0x03, 0x00, 0x00];
+
+// Maps the 2-byte local tags used in the header metadata sets to their ULs
+#[derive(Debug, Clone, Default)]
+pub struct Primer {
+    tags: HashMap<u16, UL>,
+}
+
+impl Primer {
+    // Decode the value of a Primer pack KLV: a batch of (local tag, UL) entries
+    pub fn parse(value: &[u8]) -> Result<Primer, String> {
+        if value.len() < 8 {
+            return Err(format!("primer pack too short ({} bytes)", value.len()));
+        }
+        let count = u32::from_be_bytes([value[0], value[1], value[2], value[3]]) as usize;
+        let entry_size = u32::from_be_bytes([value[4], value[5], value[6], value[7]]) as usize;
+        if entry_size != 18 {
+            return Err(format!("unexpected primer entry size {}", entry_size));
+        }
+
+        if count > (value.len() - 8) / entry_size {
+            return Err(format!("primer pack of {} entries doesn't fit in {} bytes", count, value.len()));
+        }
+        let mut tags = HashMap::with_capacity(count);
+        for i in 0..count {
+            let start = 8 + i * entry_size;
+            if start + entry_size > value.len() {
+                return Err(format!("primer pack truncated after {} entries", i));
+            }
+            let tag = u16::from_be_bytes([value[start], value[start + 1]]);
+            let mut ul = [0u8; 16];
+            ul.copy_from_slice(&value[start + 2..start + 18]);
+            tags.insert(tag, ul);
+        }
+        Ok(Primer { tags })
+    }
+
+    // Add an entry, for primers rebuilt from a text dump
+    pub fn insert(&mut self, tag: u16, ul: UL) {
+        self.tags.insert(tag, ul);
+    }
+
+    pub fn ul(&self, tag: u16) -> Option<&UL> {
+        self.tags.get(&tag)
+    }
+}
+
+// One property of a local set
+#[derive(Debug, Clone)]
+pub struct LocalItem {
+    pub tag: u16,
+    // Resolved through the primer, None when the primer doesn't know the tag
+    pub ul: Option<UL>,
+    pub value: Vec<u8>,
+    // Absolute file offset of the value, past the tag and length
+    pub value_offset: u64,
+}
+
+// A header metadata set decoded into its properties
+#[derive(Debug, Clone)]
+pub struct LocalSet {
+    pub key: UL,
+    // Absolute file offset of the set KLV
+    pub offset: u64,
+    pub items: Vec<LocalItem>,
+}
+
+impl LocalSet {
+    // Decode a local set value (2-byte tag, 2-byte length items) resolving tags with the primer
+    pub fn parse(set: &klv::Klv, value: &[u8], primer: &Primer) -> Result<LocalSet, String> {
+        let mut items = Vec::new();
+        let mut pos = 0;
+        while pos < value.len() {
+            if pos + 4 > value.len() {
+                return Err(format!("truncated local item header at {:#x}", set.value_offset + pos as u64));
+            }
+            let tag = u16::from_be_bytes([value[pos], value[pos + 1]]);
+            let len = u16::from_be_bytes([value[pos + 2], value[pos + 3]]) as usize;
+            let start = pos + 4;
+            if start + len > value.len() {
+                return Err(format!("local item {:04x} overruns its set at {:#x}", tag, set.value_offset + pos as u64));
+            }
+            items.push(LocalItem {
+                tag,
+                ul: primer.ul(tag).copied(),
+                value: value[start..start + len].to_vec(),
+                value_offset: set.value_offset + start as u64,
+            });
+            pos = start + len;
+        }
+        Ok(LocalSet { key: set.key, offset: set.offset, items })
+    }
+
+    // Find a property by its UL, whatever local tag the writer chose for it
+    pub fn find(&self, ul: &UL) -> Option<&LocalItem> {
+        self.items.iter().find(|item| item.ul.as_ref().is_some_and(|u| ul_matches(u, ul)))
+    }
+}
+
+// Compare two labels ignoring the version byte (byte 8), as SMPTE registries do
+pub fn ul_matches(a: &UL, b: &UL) -> bool {
+    a[0..7] == b[0..7] && a[8..16] == b[8..16]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACK_KEY: UL = [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0d, 0x01, 0x01, 0x01, 0x01, 0x01, 0x3b, 0x00];
+
+    fn primer_value(count: u32) -> Vec<u8> {
+        let mut value = [count.to_be_bytes(), 18u32.to_be_bytes()].concat();
+        value.extend([0x4b, 0x02]);
+        value.extend(ORIGIN_UL);
+        value
+    }
+
+    #[test]
+    fn primer_maps_tags_to_uls() {
+        let primer = Primer::parse(&primer_value(1)).unwrap();
+        assert_eq!(primer.ul(0x4b02), Some(&ORIGIN_UL));
+        assert_eq!(primer.ul(0x4b01), None);
+    }
+
+    #[test]
+    fn primer_count_is_checked_before_allocating() {
+        assert!(Primer::parse(&primer_value(2)).is_err());
+        assert!(Primer::parse(&primer_value(u32::MAX)).is_err());
+    }
+
+    #[test]
+    fn local_set_items_are_found_by_ul() {
+        let primer = Primer::parse(&primer_value(1)).unwrap();
+        let value = [vec![0x48, 0x01, 0x00, 0x04, 0, 0, 0, 2, 0x4b, 0x02, 0x00, 0x08], 16i64.to_be_bytes().to_vec()].concat();
+        let track = klv::Klv { key: TRACK_KEY, length: value.len() as u64, offset: 0x704, value_offset: 0x718 };
+        let set = LocalSet::parse(&track, &value, &primer).unwrap();
+        let origin = set.find(&ORIGIN_UL).unwrap();
+        assert_eq!((origin.tag, origin.value_offset), (0x4b02, 0x718 + 12));
+        assert_eq!(origin.value, 16i64.to_be_bytes());
+        // The Origin item overruns a cut value
+        assert!(LocalSet::parse(&track, &value[..14], &primer).is_err());
+    }
+}
@@ -0,0 +1,510 @@
+//Versioned per file record stored in the sled database
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+use sled::{Config, Db};
+use crate::analyze::{OriginFinding, OriginPolicy};
+use crate::fingerprint::Fingerprint;
+use crate::metadata::{PackageKind, Position};
+use crate::partition::PartitionKind;
+use crate::timecode::Rational;
+
+// The database lives in the working directory, one record per MXF file
+pub const DB_PATH: &str = "./file_paths_db";
+
+// Bump when the layout below changes, and keep decoding the older layouts
+// Version 2 added the content hash, version 3 the deletion time, version 4 the argv,
+// version 5 the analysis duration, version 6 the offsets of the Origin values,
+// version 7 the origin policy of the analysis
+pub const RECORD_VERSION: u8 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    // Found by the scan, not analysed yet
+    Unscanned,
+    NoOrigin,
+    HasOrigin,
+    // The analysis failed, see the record error
+    Error,
+    // Tombstone of a file that disappeared from disk, see deleted_at
+    Deleted,
+    // The dumper was killed after running longer than the timeout, see elapsed_ms
+    Timeout,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Unscanned => "Unscanned",
+            Status::NoOrigin => "NoOrigin",
+            Status::HasOrigin => "HasOrigin",
+            Status::Error => "Error",
+            Status::Deleted => "Deleted",
+            Status::Timeout => "Timeout",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Status> {
+        [Status::Unscanned, Status::NoOrigin, Status::HasOrigin, Status::Error, Status::Deleted, Status::Timeout]
+            .into_iter()
+            .find(|status| status.name() == name)
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Status::Unscanned => 0,
+            Status::NoOrigin => 1,
+            Status::HasOrigin => 2,
+            Status::Error => 3,
+            Status::Deleted => 4,
+            Status::Timeout => 5,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Status, String> {
+        match byte {
+            0 => Ok(Status::Unscanned),
+            1 => Ok(Status::NoOrigin),
+            2 => Ok(Status::HasOrigin),
+            3 => Ok(Status::Error),
+            4 => Ok(Status::Deleted),
+            5 => Ok(Status::Timeout),
+            _ => Err(format!("unknown status {}", byte)),
+        }
+    }
+}
+
+// One Origin property as stored in the database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackOrigin {
+    pub partition: PartitionKind,
+    pub package: PackageKind,
+    pub track_id: Option<u32>,
+    pub track_name: Option<String>,
+    pub edit_rate: Option<Rational>,
+    pub origin: Position,
+    // Absolute file offset of the 8-byte Origin value, None when the dumper doesn't tell
+    pub origin_offset: Option<u64>,
+}
+
+impl TrackOrigin {
+    pub fn from_finding(finding: &OriginFinding) -> TrackOrigin {
+        TrackOrigin {
+            partition: finding.partition,
+            package: finding.package.kind,
+            track_id: finding.track.track_id,
+            track_name: finding.track.name.clone(),
+            edit_rate: finding.track.edit_rate,
+            origin: finding.origin,
+            origin_offset: finding.track.origin_offset,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileRecord {
+    pub status: Status,
+    pub origins: Vec<TrackOrigin>,
+    pub file_size: u64,
+    // Modification time of the file, seconds since the Unix epoch
+    pub mtime: u64,
+    // Optional hash of the file content, see fingerprint.rs
+    pub content_hash: Option<u64>,
+    // When the analysis ran, seconds since the Unix epoch (0 when never analysed)
+    pub analysed_at: u64,
+    // When the file was found missing, seconds since the Unix epoch (0 when present)
+    pub deleted_at: u64,
+    // How long the last analysis ran, in milliseconds
+    pub elapsed_ms: u64,
+    pub dumper_version: String,
+    // Exact invocation of the last analysis, program then arguments
+    pub argv: Vec<String>,
+    pub error: Option<String>,
+    // Package types whose Origins made the status HasOrigin, older records count both
+    pub policy: OriginPolicy,
+}
+
+impl FileRecord {
+    // Record of a file the scan just found (or found modified)
+    pub fn unscanned(fingerprint: &Fingerprint) -> FileRecord {
+        FileRecord {
+            status: Status::Unscanned,
+            origins: Vec::new(),
+            file_size: fingerprint.file_size,
+            mtime: fingerprint.mtime,
+            content_hash: fingerprint.content_hash,
+            analysed_at: 0,
+            deleted_at: 0,
+            elapsed_ms: 0,
+            dumper_version: String::new(),
+            argv: Vec::new(),
+            error: None,
+            policy: OriginPolicy::Both,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Encoder(Vec::new());
+        out.u8(RECORD_VERSION);
+        out.u8(self.status.to_byte());
+        out.u64(self.file_size);
+        out.u64(self.mtime);
+        match self.content_hash {
+            Some(hash) => {
+                out.u8(1);
+                out.u64(hash);
+            }
+            None => out.u8(0),
+        }
+        out.u64(self.analysed_at);
+        out.u64(self.deleted_at);
+        out.str(&self.dumper_version);
+        out.opt_str(self.error.as_deref());
+        out.u32(self.origins.len() as u32);
+        for origin in &self.origins {
+            out.u8(partition_to_byte(origin.partition));
+            out.u8(package_to_byte(origin.package));
+            match origin.track_id {
+                Some(id) => {
+                    out.u8(1);
+                    out.u32(id);
+                }
+                None => out.u8(0),
+            }
+            out.opt_str(origin.track_name.as_deref());
+            match origin.edit_rate {
+                Some(rate) => {
+                    out.u8(1);
+                    out.u32(rate.numerator as u32);
+                    out.u32(rate.denominator as u32);
+                }
+                None => out.u8(0),
+            }
+            out.u64(origin.origin as u64);
+        }
+        out.u32(self.argv.len() as u32);
+        for arg in &self.argv {
+            out.str(arg);
+        }
+        out.u64(self.elapsed_ms);
+        for origin in &self.origins {
+            match origin.origin_offset {
+                Some(offset) => {
+                    out.u8(1);
+                    out.u64(offset);
+                }
+                None => out.u8(0),
+            }
+        }
+        out.u8(policy_to_byte(self.policy));
+        out.0
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<FileRecord, String> {
+        let mut input = Decoder { bytes, pos: 0 };
+        let version = input.u8()?;
+        if version == 0 || version > RECORD_VERSION {
+            return Err(format!("unsupported record version {}", version));
+        }
+        let status = Status::from_byte(input.u8()?)?;
+        let file_size = input.u64()?;
+        let mtime = input.u64()?;
+        let content_hash = if version >= 2 && input.u8()? == 1 { Some(input.u64()?) } else { None };
+        let analysed_at = input.u64()?;
+        let deleted_at = if version >= 3 { input.u64()? } else { 0 };
+        let dumper_version = input.str()?;
+        let error = input.opt_str()?;
+        let count = input.u32()?;
+        let mut origins = Vec::new();
+        for _ in 0..count {
+            let partition = partition_from_byte(input.u8()?)?;
+            let package = package_from_byte(input.u8()?)?;
+            let track_id = if input.u8()? == 1 { Some(input.u32()?) } else { None };
+            let track_name = input.opt_str()?;
+            let edit_rate = if input.u8()? == 1 {
+                Some(Rational { numerator: input.u32()? as i32, denominator: input.u32()? as i32 })
+            } else {
+                None
+            };
+            let origin = input.u64()? as Position;
+            origins.push(TrackOrigin { partition, package, track_id, track_name, edit_rate, origin, origin_offset: None });
+        }
+        let mut argv = Vec::new();
+        if version >= 4 {
+            for _ in 0..input.u32()? {
+                argv.push(input.str()?);
+            }
+        }
+        let elapsed_ms = if version >= 5 { input.u64()? } else { 0 };
+        if version >= 6 {
+            for origin in &mut origins {
+                origin.origin_offset = if input.u8()? == 1 { Some(input.u64()?) } else { None };
+            }
+        }
+        let policy = if version >= 7 { policy_from_byte(input.u8()?)? } else { OriginPolicy::Both };
+        Ok(FileRecord {
+            status,
+            origins,
+            file_size,
+            mtime,
+            content_hash,
+            analysed_at,
+            deleted_at,
+            elapsed_ms,
+            dumper_version,
+            argv,
+            error,
+            policy,
+        })
+    }
+}
+
+// Decode a database value, values written before records existed (0u8, "true") count as unscanned
+pub fn load(bytes: &[u8]) -> FileRecord {
+    FileRecord::decode(bytes).unwrap_or_else(|_| FileRecord::unscanned(&Fingerprint { file_size: 0, mtime: 0, content_hash: None }))
+}
+
+// Database keys are the base64 of the file path
+pub fn decodeb64(file_path_b64: &str) -> Result<String,String> {
+    match base64::decode(file_path_b64) {
+        Ok(decoded_bytes) => {
+            match String::from_utf8(decoded_bytes){
+                Ok(decoded_string) => Ok(decoded_string),
+                Err(err) => {
+                    eprintln!("Couldn't decode the base64 value as UTF-8 {err}");
+                    Err("Invalid UTF-8".to_string())
+                }
+            }
+        }
+        Err(err) => {
+            eprintln!("Couldn't decode the base64 value {err}");
+            Err("Invalid UTF-8".to_string())
+        }
+    }
+}
+
+// Absolute path and database key of a file given on the command line, as the scan stores them
+pub fn key_of(file_path: &str) -> (String, String) {
+    let file_path = fs::canonicalize(file_path).map_or(file_path.to_string(), |p| p.to_string_lossy().into_owned());
+    let key = base64::encode(file_path.as_bytes());
+    (file_path, key)
+}
+
+// Open the database of the working directory
+pub fn open_db() -> io::Result<Db> {
+    Ok(Config::new().path(DB_PATH).open()?)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+pub fn to_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn partition_to_byte(kind: PartitionKind) -> u8 {
+    match kind {
+        PartitionKind::Header => 0,
+        PartitionKind::Body => 1,
+        PartitionKind::Footer => 2,
+    }
+}
+
+fn partition_from_byte(byte: u8) -> Result<PartitionKind, String> {
+    match byte {
+        0 => Ok(PartitionKind::Header),
+        1 => Ok(PartitionKind::Body),
+        2 => Ok(PartitionKind::Footer),
+        _ => Err(format!("unknown partition kind {}", byte)),
+    }
+}
+
+fn package_to_byte(kind: PackageKind) -> u8 {
+    match kind {
+        PackageKind::Material => 0,
+        PackageKind::Source => 1,
+        PackageKind::Other => 2,
+    }
+}
+
+fn package_from_byte(byte: u8) -> Result<PackageKind, String> {
+    match byte {
+        0 => Ok(PackageKind::Material),
+        1 => Ok(PackageKind::Source),
+        2 => Ok(PackageKind::Other),
+        _ => Err(format!("unknown package kind {}", byte)),
+    }
+}
+
+fn policy_to_byte(policy: OriginPolicy) -> u8 {
+    match policy {
+        OriginPolicy::Material => 0,
+        OriginPolicy::Source => 1,
+        OriginPolicy::Both => 2,
+    }
+}
+
+fn policy_from_byte(byte: u8) -> Result<OriginPolicy, String> {
+    match byte {
+        0 => Ok(OriginPolicy::Material),
+        1 => Ok(OriginPolicy::Source),
+        2 => Ok(OriginPolicy::Both),
+        _ => Err(format!("unknown origin policy {}", byte)),
+    }
+}
+
+// Big endian writer for the record layout
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn opt_str(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                self.str(value);
+            }
+            None => self.u8(0),
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], String> {
+        if self.pos + count > self.bytes.len() {
+            return Err("record truncated".to_string());
+        }
+        let slice = &self.bytes[self.pos..self.pos + count];
+        self.pos += count;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn opt_str(&mut self) -> Result<Option<String>, String> {
+        if self.u8()? == 1 { Ok(Some(self.str()?)) } else { Ok(None) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> FileRecord {
+        FileRecord {
+            status: Status::HasOrigin,
+            origins: vec![
+                TrackOrigin {
+                    partition: PartitionKind::Footer,
+                    package: PackageKind::Material,
+                    track_id: Some(2),
+                    track_name: Some("Sound".to_string()),
+                    edit_rate: Some(Rational { numerator: 48000, denominator: 1 }),
+                    origin: -16,
+                    origin_offset: Some(0x571e00),
+                },
+                TrackOrigin {
+                    partition: PartitionKind::Header,
+                    package: PackageKind::Other,
+                    track_id: None,
+                    track_name: None,
+                    edit_rate: None,
+                    origin: Position::MIN,
+                    origin_offset: None,
+                },
+            ],
+            file_size: 7024138,
+            mtime: 1792303298,
+            content_hash: Some(u64::MAX),
+            analysed_at: 1792303299,
+            deleted_at: 1792303300,
+            elapsed_ms: 1234,
+            dumper_version: "native 0.1.0".to_string(),
+            argv: vec!["native".to_string(), "/media/caf\u{e9}.mxf".to_string()],
+            error: Some("Track not found".to_string()),
+            policy: OriginPolicy::Material,
+        }
+    }
+
+    #[test]
+    fn every_field_survives_the_record() {
+        let filerecord = record();
+        assert_eq!(FileRecord::decode(&filerecord.encode()).unwrap(), filerecord);
+        let fingerprint = Fingerprint { file_size: 1, mtime: 2, content_hash: None };
+        let unscanned = FileRecord::unscanned(&fingerprint);
+        assert_eq!(FileRecord::decode(&unscanned.encode()).unwrap(), unscanned);
+    }
+
+    #[test]
+    fn older_versions_are_still_decoded() {
+        // Version 6 ends before the policy, counted as both package types
+        let mut filerecord = record();
+        let mut bytes = filerecord.encode();
+        bytes.truncate(bytes.len() - 1);
+        bytes[0] = 6;
+        filerecord.policy = OriginPolicy::Both;
+        assert_eq!(FileRecord::decode(&bytes).unwrap(), filerecord);
+
+        // Version 5 also ends before the Origin offsets, one byte per origin here
+        filerecord.origins[0].origin_offset = None;
+        bytes = filerecord.encode();
+        bytes.truncate(bytes.len() - 3);
+        bytes[0] = 5;
+        assert_eq!(FileRecord::decode(&bytes).unwrap(), filerecord);
+    }
+
+    #[test]
+    fn legacy_values_load_as_unscanned() {
+        assert_eq!(load(&[0u8]).status, Status::Unscanned);
+        assert_eq!(load(b"true").status, Status::Unscanned);
+        assert_eq!(load(&[]).status, Status::Unscanned);
+    }
+
+    #[test]
+    fn truncated_records_are_errors() {
+        let bytes = record().encode();
+        for length in 0..bytes.len() {
+            assert!(FileRecord::decode(&bytes[..length]).is_err(), "{} of {} bytes", length, bytes.len());
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//Reports and queries on the results stored in the database
+use std::io::{self, Write};
+use clap::ArgMatches;
+use sled::Db;
+use crate::exit::Outcome;
+use crate::export::{self, Format};
+use crate::record::{self, FileRecord, Status, TrackOrigin};
+
+// Print every analysed file with its status and flagged Origins
+pub fn run_report(args: &ArgMatches) -> io::Result<Outcome> {
+    let verbose = args.is_present("verbose");
+    let format = args.value_of("format").and_then(Format::from_name).unwrap_or(Format::Text);
+    let db = record::open_db()?;
+
+    // Machine readable formats list every record, tools do their own filtering
+    if format != Format::Text {
+        let stdout = io::stdout();
+        let mut out = io::BufWriter::new(stdout.lock());
+        export::write_records(&mut out, format, &records(&db))?;
+        out.flush()?;
+        return Ok(outcome(&db));
+    }
+
+    for (file_path, filerecord) in records(&db) {
+        if !verbose && matches!(filerecord.status, Status::NoOrigin | Status::Deleted) {
+            continue;
+        }
+        print_record(&file_path, &filerecord, verbose);
+    }
+    println!();
+    print_summary(&db);
+    Ok(outcome(&db))
+}
+
+// Print the records of the given paths, or of every file having a status
+// The outcome only covers the files looked up by path
+pub fn run_query(args: &ArgMatches) -> io::Result<Outcome> {
+    let db = record::open_db()?;
+    let mut outcome = Outcome::Clean;
+
+    if let Some(status) = args.value_of("status").and_then(Status::from_name) {
+        for (file_path, filerecord) in records(&db) {
+            if filerecord.status == status {
+                println!("{}", file_path);
+            }
+        }
+    }
+    for file_path in args.values_of("paths").into_iter().flatten() {
+        let (file_path, key) = record::key_of(file_path);
+        match db.get(key.as_bytes()) {
+            Ok(Some(value)) => {
+                let filerecord = record::load(&value);
+                outcome = outcome.max(Outcome::of(filerecord.status));
+                print_record(&file_path, &filerecord, true);
+            }
+            Ok(None) => println!("{} : not in the database", file_path),
+            Err(e) => eprintln!("Error reading {} from the database : {}", file_path, e),
+        }
+    }
+    Ok(outcome)
+}
+
+// Worst outcome of the files of the database
+pub fn outcome(db: &Db) -> Outcome {
+    records(db)
+        .iter()
+        .map(|(_, filerecord)| Outcome::of(filerecord.status))
+        .max()
+        .unwrap_or(Outcome::Clean)
+}
+
+// Count the database records by status
+pub fn print_summary(db: &Db) {
+    let mut counts = [
+        (Status::Unscanned, 0),
+        (Status::NoOrigin, 0),
+        (Status::HasOrigin, 0),
+        (Status::Error, 0),
+        (Status::Deleted, 0),
+        (Status::Timeout, 0),
+    ];
+    for (_key, value) in db.iter().flatten() {
+        let status = record::load(&value).status;
+        if let Some(entry) = counts.iter_mut().find(|(s, _)| *s == status) {
+            entry.1 += 1;
+        }
+    }
+    println!("--- Database summary ---");
+    for (status, count) in counts {
+        println!("{:>10} : {}", status.name(), count);
+    }
+}
+
+fn print_record(file_path: &str, filerecord: &FileRecord, verbose: bool) {
+    println!("{} : {}", file_path, filerecord.status.name());
+    if let Some(error) = &filerecord.error {
+        println!("  error: {}", error);
+    }
+    for origin in &filerecord.origins {
+        if verbose || origin.origin != 0 {
+            println!("  {}", describe_origin(origin));
+        }
+    }
+    if verbose {
+        println!(
+            "  size = {}, mtime = {}, analysed at = {} in {} ms, by = {}",
+            filerecord.file_size, filerecord.mtime, filerecord.analysed_at, filerecord.elapsed_ms, filerecord.dumper_version
+        );
+        if !filerecord.argv.is_empty() {
+            println!("  argv = {}", filerecord.argv.join(" "));
+        }
+    }
+}
+
+fn describe_origin(origin: &TrackOrigin) -> String {
+    format!(
+        "Origin = {} @ {} in {} partition, {} / track {} {}{}",
+        origin.origin,
+        origin.edit_rate.map_or("-".to_string(), |rate| rate.to_string()),
+        origin.partition.name(),
+        origin.package.name(),
+        origin.track_id.map_or("?".to_string(), |id| id.to_string()),
+        origin.track_name.as_deref().unwrap_or(""),
+        origin.origin_offset.map_or(String::new(), |offset| format!(" (value at {:#x})", offset))
+    )
+}
+
+// Decoded (path, record) pairs of the whole database
+fn records(db: &Db) -> Vec<(String, FileRecord)> {
+    db.iter()
+        .flatten()
+        .filter_map(|(key, value)| {
+            let key = String::from_utf8(key.to_vec()).ok()?;
+            let file_path = record::decodeb64(&key).ok()?;
+            Some((file_path, record::load(&value)))
+        })
+        .collect()
+}
@@ -0,0 +1,119 @@
+//Random Index Pack, the table of partitions at the very end of a file
+use std::io::{self, Read, Seek, SeekFrom};
+use crate::klv::{self, UL};
+
+// Key of the Random Index Pack (MXFDump's RandomIndexMetadata)
+pub const RIP_KEY: UL = [
+    0x06, 0x0e, 0x2b, 0x34, 0x02, 0x05, 0x01, 0x01, 0x0d, 0x01, 0x02, 0x01, 0x01, 0x11, 0x01, 0x00,
+];
+
+// One partition listed by the pack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RipEntry {
+    pub body_sid: u32,
+    // Absolute file offset of the partition pack
+    pub offset: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RandomIndexPack {
+    // Absolute file offset of the pack key
+    pub offset: u64,
+    pub entries: Vec<RipEntry>,
+}
+
+impl RandomIndexPack {
+    // Decode the value of the pack: (BodySID, ByteOffset) pairs then the overall length
+    pub fn parse(offset: u64, value: &[u8]) -> Result<RandomIndexPack, String> {
+        if value.len() < 4 || !(value.len() - 4).is_multiple_of(12) {
+            return Err(format!("unexpected Random Index Pack length {}", value.len()));
+        }
+        let entries = value[..value.len() - 4]
+            .chunks(12)
+            .map(|entry| {
+                let mut body_sid = [0u8; 4];
+                let mut offset = [0u8; 8];
+                body_sid.copy_from_slice(&entry[0..4]);
+                offset.copy_from_slice(&entry[4..12]);
+                RipEntry { body_sid: u32::from_be_bytes(body_sid), offset: u64::from_be_bytes(offset) }
+            })
+            .collect();
+        Ok(RandomIndexPack { offset, entries })
+    }
+
+    // The footer is the last partition of the file
+    pub fn footer_offset(&self) -> Option<u64> {
+        self.entries.iter().map(|entry| entry.offset).max()
+    }
+}
+
+// Look for the pack at the end of the file, its last 4 bytes give its overall length.
+// Returns None when the file doesn't end with one.
+pub fn read_rip<R: Read + Seek>(reader: &mut R) -> io::Result<Option<RandomIndexPack>> {
+    let file_size = reader.seek(SeekFrom::End(0))?;
+    if file_size < 20 {
+        return Ok(None);
+    }
+    let mut length = [0u8; 4];
+    reader.seek(SeekFrom::Start(file_size - 4))?;
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as u64;
+    // Key, BER length and the length field itself at least
+    if length < 21 || length > file_size {
+        return Ok(None);
+    }
+
+    let offset = file_size - length;
+    let mut key = [0u8; 16];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut key)?;
+    if key != RIP_KEY {
+        return Ok(None);
+    }
+    let (value_length, length_size) = klv::read_ber_length(reader)?;
+    if length_size.checked_add(value_length).and_then(|size| size.checked_add(16)) != Some(length) {
+        return Ok(None);
+    }
+    let mut value = vec![0; value_length as usize];
+    reader.read_exact(&mut value)?;
+    RandomIndexPack::parse(offset, &value)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Essence, then the pack listing a header and a footer
+    fn file_with_rip(ber: &[u8]) -> Vec<u8> {
+        let entries = [RipEntry { body_sid: 0, offset: 0 }, RipEntry { body_sid: 1, offset: 0x571429 }];
+        let mut value = Vec::new();
+        for entry in entries {
+            value.extend(entry.body_sid.to_be_bytes());
+            value.extend(entry.offset.to_be_bytes());
+        }
+        let length = (16 + ber.len() + value.len() + 4) as u32;
+        value.extend(length.to_be_bytes());
+        [vec![0; 64], RIP_KEY.to_vec(), ber.to_vec(), value].concat()
+    }
+
+    #[test]
+    fn pack_is_read_from_the_end_of_the_file() {
+        let rip = read_rip(&mut Cursor::new(file_with_rip(&[0x83, 0, 0, 28]))).unwrap().unwrap();
+        assert_eq!(rip.offset, 64);
+        assert_eq!(rip.entries, [RipEntry { body_sid: 0, offset: 0 }, RipEntry { body_sid: 1, offset: 0x571429 }]);
+        assert_eq!(rip.footer_offset(), Some(0x571429));
+        // Short form BER too
+        assert!(read_rip(&mut Cursor::new(file_with_rip(&[28]))).unwrap().is_some());
+    }
+
+    #[test]
+    fn file_without_pack_has_none() {
+        assert_eq!(read_rip(&mut Cursor::new(vec![0; 64])).unwrap(), None);
+        // 8-byte BER length that would overflow the overall length check
+        let file = file_with_rip(&[0x88, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(read_rip(&mut Cursor::new(file)).unwrap(), None);
+    }
+}
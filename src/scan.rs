@@ -1,30 +1,29 @@
 //scan directory
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::thread;
 use walkdir::WalkDir;
-use sled::Config;
-use base64;
+use sled::Db;
+use crate::fingerprint::Fingerprint;
+use crate::record::{self, FileRecord, Status};
 
 
-pub fn scandir(directory: &String, verbose: bool) {
-    let dir_path = Path::new(&directory);
+// Add new MXF files to the database and mark modified ones for reanalysis
+pub fn scandir(db: &Db, directory: &str, verbose: bool, with_hash: bool) -> io::Result<()> {
+    let dir_path = root(directory);
     let (tx, rx) = mpsc::channel();
 
     // Spawn a thread to scan the directory
     let dir_path_clone = dir_path.to_path_buf();
     thread::spawn(move || scan_directory(&dir_path_clone, tx));
 
-    // Initialize the sled database
-    let db = Config::new()
-        .path("./file_paths_db")
-        .open()
-        .unwrap();
+    let (mut new, mut changed, mut unchanged) = (0, 0, 0);
 
     // Receive and process file paths
     for file_path in rx {
         let file_path_str = file_path.to_string_lossy().into_owned();
-        let pattern_found = 0u8; //By default we assume their is no Origin/Precharge
         
         let file_path_b64 = base64::encode(file_path_str.as_bytes());
         if verbose {
@@ -47,24 +46,92 @@ pub fn scandir(directory: &String, verbose: bool) {
             }
         }
 
+        let fingerprint = match Fingerprint::of(&file_path, with_hash) {
+            Ok(fingerprint) => fingerprint,
+            Err(e) => {
+                eprintln!("Couldn't read {} : {}", file_path.display(), e);
+                continue;
+            }
+        };
+
         // Check if the file already exists in the database
-        if let Ok(Some(_value)) = db.get(file_path_b64.as_bytes()) {
+        if let Ok(Some(value)) = db.get(file_path_b64.as_bytes()) {
+            let mut filerecord = record::load(&value);
+            // A tombstoned file that came back is reanalysed like a modified one
+            if fingerprint.matches(&filerecord) && filerecord.status != Status::Deleted {
+                unchanged += 1;
                 if verbose {
                     println!("File already exists in database: {}", file_path.display());
                 }
+                // Remember the hash the first time one is computed for this file
+                if filerecord.content_hash.is_none() && fingerprint.content_hash.is_some() {
+                    filerecord.content_hash = fingerprint.content_hash;
+                    let _ = db.insert(file_path_b64.as_bytes(), filerecord.encode());
+                }
             } else {
+                changed += 1;
                 if verbose {
-                    println!("Found new MXF file: {}", file_path.display());
+                    println!("Modified MXF file, marked for reanalysis: {}", file_path.display());
                 }
-                // Save the file path to the sled database if doesn't already exist
-                // When a file path is added it's value is systematically set to false
-                // we assume there is no Origin/Precharge by default
-                let _ = db.insert(file_path_b64.as_bytes(), &[pattern_found]);
+                let _ = db.insert(file_path_b64.as_bytes(), FileRecord::unscanned(&fingerprint).encode());
             }
+        } else {
+            new += 1;
+            if verbose {
+                println!("Found new MXF file: {}", file_path.display());
+            }
+            // Save the file path to the sled database if doesn't already exist
+            // When a file path is added it's record is Unscanned until the analysis runs
+            let _ = db.insert(file_path_b64.as_bytes(), FileRecord::unscanned(&fingerprint).encode());
+        }
     }
     
+    println!("Scan done: {} new, {} modified, {} unchanged MXF file(s).", new, changed, unchanged);
     // Flush all changes to disk before exiting
-    db.flush().unwrap();
+    db.flush()?;
+    Ok(())
+}
+
+// Keys are absolute paths so that later lookups don't depend on the working directory
+pub fn root(directory: &str) -> PathBuf {
+    fs::canonicalize(directory).unwrap_or_else(|_| Path::new(directory).to_path_buf())
+}
+
+// Find the database entries under the scanned folders whose file no longer exists and
+// either remove them or mark them as tombstones, returns the pruned paths. Files of
+// other folders are left alone, their disk may just be offline, and so are the
+// folders that can't be listed right now.
+pub fn reconcile(db: &Db, roots: &[PathBuf], remove: bool, verbose: bool) -> io::Result<Vec<String>> {
+    let roots: Vec<&PathBuf> = roots.iter().filter(|root| root.is_dir()).collect();
+    let mut pruned = Vec::new();
+    for (key, value) in db.iter().flatten() {
+        let Some(file_path) = base64::decode(&key).ok().and_then(|bytes| String::from_utf8(bytes).ok()) else {
+            eprintln!("couldn't decode the key");
+            continue;
+        };
+        if !roots.iter().any(|root| Path::new(&file_path).starts_with(root)) {
+            continue;
+        }
+        let mut filerecord = record::load(&value);
+        if Path::new(&file_path).exists() || (filerecord.status == Status::Deleted && !remove) {
+            continue;
+        }
+
+        if remove {
+            let _ = db.remove(&key);
+        } else {
+            filerecord.status = Status::Deleted;
+            filerecord.deleted_at = record::now();
+            let _ = db.insert(&key, filerecord.encode());
+        }
+        if verbose {
+            println!("Missing MXF file {}: {}", if remove { "removed" } else { "tombstoned" }, file_path);
+        }
+        pruned.push(file_path);
+    }
+
+    db.flush()?;
+    Ok(pruned)
 }
 
 fn scan_directory(dir: &Path, tx: mpsc::Sender<PathBuf>) {
@@ -78,3 +145,106 @@ fn scan_directory(dir: &Path, tx: mpsc::Sender<PathBuf>) {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fingerprint;
+
+    // Folder holding one MXF file, and an empty database
+    fn folder(name: &str) -> (PathBuf, PathBuf, Db) {
+        let dir = std::env::temp_dir().join(format!("whereismyorigin-scan-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("clip.mxf");
+        fs::write(&file_path, [0u8; 64]).unwrap();
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        (dir, fs::canonicalize(file_path).unwrap(), db)
+    }
+
+    fn stored(db: &Db, file_path: &Path) -> FileRecord {
+        record::load(&db.get(base64::encode(file_path.to_string_lossy().as_bytes())).unwrap().unwrap())
+    }
+
+    fn save(db: &Db, file_path: &Path, filerecord: &FileRecord) {
+        db.insert(base64::encode(file_path.to_string_lossy().as_bytes()), filerecord.encode()).unwrap();
+    }
+
+    fn scan(db: &Db, dir: &Path, with_hash: bool) {
+        scandir(db, &dir.to_string_lossy(), false, with_hash).unwrap();
+    }
+
+    #[test]
+    fn hash_is_added_to_an_unchanged_record() {
+        let (dir, file_path, db) = folder("hash");
+        scan(&db, &dir, false);
+        assert_eq!(stored(&db, &file_path).content_hash, None);
+
+        let mut filerecord = stored(&db, &file_path);
+        filerecord.status = Status::NoOrigin;
+        save(&db, &file_path, &filerecord);
+        scan(&db, &dir, true);
+        // Still the same file, its analysis is kept
+        let filerecord = stored(&db, &file_path);
+        assert_eq!(filerecord.status, Status::NoOrigin);
+        assert_eq!(filerecord.content_hash, Some(fingerprint::content_hash(&file_path).unwrap()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn modified_or_returning_files_are_analysed_again() {
+        let (dir, file_path, db) = folder("reset");
+        scan(&db, &dir, false);
+        let mut filerecord = stored(&db, &file_path);
+        filerecord.status = Status::HasOrigin;
+        save(&db, &file_path, &filerecord);
+
+        fs::write(&file_path, [0u8; 128]).unwrap();
+        scan(&db, &dir, false);
+        let filerecord = stored(&db, &file_path);
+        assert_eq!((filerecord.status, filerecord.file_size), (Status::Unscanned, 128));
+
+        // A tombstone of the same size and mtime, the file came back unchanged
+        let mut filerecord = filerecord;
+        filerecord.status = Status::Deleted;
+        filerecord.deleted_at = 1;
+        save(&db, &file_path, &filerecord);
+        scan(&db, &dir, false);
+        let filerecord = stored(&db, &file_path);
+        assert_eq!((filerecord.status, filerecord.deleted_at), (Status::Unscanned, 0));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_missing_files_under_the_roots_are_reconciled() {
+        let (dir, file_path, db) = folder("reconcile");
+        let root = fs::canonicalize(&dir).unwrap();
+        let unscanned = FileRecord::unscanned(&Fingerprint { file_size: 64, mtime: 0, content_hash: None });
+        save(&db, &file_path, &unscanned);
+        let missing = root.join("gone.mxf");
+        // Same prefix but another folder, and a folder on a disk that may be offline
+        let sibling = PathBuf::from(format!("{}-other", root.display())).join("gone.mxf");
+        let outside = PathBuf::from("/media/offline/gone.mxf");
+        for path in [&missing, &sibling, &outside] {
+            save(&db, path, &unscanned);
+        }
+
+        let pruned = reconcile(&db, std::slice::from_ref(&root), false, false).unwrap();
+        assert_eq!(pruned, [missing.to_string_lossy()]);
+        let tombstone = stored(&db, &missing);
+        assert_eq!(tombstone.status, Status::Deleted);
+        assert!(tombstone.deleted_at > 0);
+        for path in [&file_path, &sibling, &outside] {
+            assert_eq!(stored(&db, path).status, Status::Unscanned, "{}", path.display());
+        }
+        // A tombstone is only reported once, pruning removes it
+        assert!(reconcile(&db, std::slice::from_ref(&root), false, false).unwrap().is_empty());
+        assert_eq!(reconcile(&db, std::slice::from_ref(&root), true, false).unwrap(), [missing.to_string_lossy()]);
+        assert!(db.get(base64::encode(missing.to_string_lossy().as_bytes())).unwrap().is_none());
+        assert_eq!(db.len(), 3);
+
+        // A root that can't be listed reconciles nothing
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(reconcile(&db, &[root], true, false).unwrap().is_empty());
+        assert_eq!(db.len(), 3);
+    }
+}
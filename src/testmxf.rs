@@ -0,0 +1,192 @@
+//Small MXF files generated for the tests
+use std::fs;
+use crate::klv::UL;
+use crate::metadata::{self, Position};
+use crate::partition::PartitionPack;
+use crate::primer::ORIGIN_UL;
+use crate::rip::RIP_KEY;
+
+// Partition kinds, byte 13 of the partition pack key
+pub const HEADER: u8 = 0x02;
+pub const BODY: u8 = 0x03;
+pub const FOOTER: u8 = 0x04;
+
+// Offsets of the partition pack fields in its value
+pub const FOOTER_PARTITION: usize = 24;
+pub const HEADER_BYTE_COUNT: usize = 32;
+
+// Local tags of the primer, the usual ones of SMPTE 377
+const TAGS: [(u16, UL); 10] = [
+    (0x3c0a, metadata::INSTANCE_UID),
+    (0x3b03, metadata::CONTENT_STORAGE),
+    (0x1901, metadata::PACKAGES),
+    (0x4402, metadata::PACKAGE_NAME),
+    (0x4403, metadata::TRACKS),
+    (0x4801, metadata::TRACK_ID),
+    (0x4803, metadata::SEQUENCE),
+    (0x4b01, metadata::EDIT_RATE),
+    (0x4b02, ORIGIN_UL),
+    (0x1001, metadata::STRUCTURAL_COMPONENTS),
+];
+
+// Key of a header metadata set, e.g. 0x2f for the Preface
+pub fn set_key(kind: u8) -> UL {
+    [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0d, 0x01, 0x01, 0x01, 0x01, 0x01, kind, 0x00]
+}
+
+pub fn uid(n: u8) -> UL {
+    let mut uid = [0u8; 16];
+    uid[15] = n;
+    uid
+}
+
+// Local item: 2-byte tag, 2-byte length, value
+pub fn item(tag: u16, value: &[u8]) -> Vec<u8> {
+    [tag.to_be_bytes().to_vec(), (value.len() as u16).to_be_bytes().to_vec(), value.to_vec()].concat()
+}
+
+// Strong reference batch: count, item size, UIDs
+pub fn batch(uids: &[UL]) -> Vec<u8> {
+    [(uids.len() as u32).to_be_bytes().to_vec(), 16u32.to_be_bytes().to_vec(), uids.concat()].concat()
+}
+
+pub fn primer() -> Vec<u8> {
+    let mut value = [(TAGS.len() as u32).to_be_bytes(), 18u32.to_be_bytes()].concat();
+    for (tag, ul) in TAGS {
+        value.extend(tag.to_be_bytes());
+        value.extend(ul);
+    }
+    value
+}
+
+// Preface -> ContentStorage -> a material and a source package, each with one
+// 25/1 track, its sequence and a source clip. UIDs: 1 Preface, 2 ContentStorage,
+// 3 and 4 the packages, 0x30 and 0x40 their tracks, then the sequence and the clip
+pub fn sets(material_origin: Position, source_origin: Position) -> Vec<(UL, Vec<u8>)> {
+    let mut sets = vec![
+        (set_key(0x2f), [item(0x3c0a, &uid(1)), item(0x3b03, &uid(2))].concat()),
+        (set_key(0x18), [item(0x3c0a, &uid(2)), item(0x1901, &batch(&[uid(3), uid(4)]))].concat()),
+    ];
+    for (package, key, origin) in [(3u8, 0x36, material_origin), (4, 0x37, source_origin)] {
+        let track = package * 0x10;
+        sets.push((set_key(key), [item(0x3c0a, &uid(package)), item(0x4403, &batch(&[uid(track)]))].concat()));
+        sets.push((
+            set_key(0x3b),
+            [
+                item(0x3c0a, &uid(track)),
+                item(0x4801, &1u32.to_be_bytes()),
+                item(0x4b01, &[25i32.to_be_bytes(), 1i32.to_be_bytes()].concat()),
+                item(0x4b02, &origin.to_be_bytes()),
+                item(0x4803, &uid(track + 1)),
+            ]
+            .concat(),
+        ));
+        sets.push((set_key(0x0f), [item(0x3c0a, &uid(track + 1)), item(0x1001, &batch(&[uid(track + 2)]))].concat()));
+        sets.push((set_key(0x11), item(0x3c0a, &uid(track + 2))));
+    }
+    sets
+}
+
+// KLV with a 4-byte BER length
+pub fn klv(key: &UL, value: &[u8]) -> Vec<u8> {
+    let length = (value.len() as u32).to_be_bytes();
+    [key.to_vec(), vec![0x83, length[1], length[2], length[3]], value.to_vec()].concat()
+}
+
+// Decoded partition pack, as MxfFile::partition() writes it
+pub fn partition_pack(kind: u8, closed: bool, complete: bool) -> PartitionPack {
+    let file = MxfFile::default().partition(kind, closed, complete);
+    let key: UL = file.bytes[..16].try_into().unwrap();
+    PartitionPack::parse(&key, &file.bytes[20..]).unwrap()
+}
+
+#[derive(Default)]
+pub struct MxfFile {
+    pub bytes: Vec<u8>,
+    // Offsets of the partition packs, in file order
+    pub partitions: Vec<u64>,
+}
+
+impl MxfFile {
+    // Partition pack without essence containers, metadata() sets its HeaderByteCount
+    pub fn partition(mut self, kind: u8, closed: bool, complete: bool) -> MxfFile {
+        let status = match (closed, complete) {
+            (false, false) => 0x01,
+            (true, false) => 0x02,
+            (false, true) => 0x03,
+            (true, true) => 0x04,
+        };
+        let key = [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x05, 0x01, 0x01, 0x0d, 0x01, 0x02, 0x01, 0x01, kind, status, 0x00];
+        let offset = self.bytes.len() as u64;
+        let mut value = Vec::new();
+        value.extend(1u16.to_be_bytes());
+        value.extend(3u16.to_be_bytes());
+        value.extend(1u32.to_be_bytes());
+        value.extend(offset.to_be_bytes());
+        value.extend(self.partitions.last().copied().unwrap_or(0).to_be_bytes());
+        value.extend([0u8; 16]);
+        value.extend(0u64.to_be_bytes());
+        value.extend(0u32.to_be_bytes());
+        value.extend(0u64.to_be_bytes());
+        value.extend(1u32.to_be_bytes());
+        value.extend([0x06, 0x0e, 0x2b, 0x34, 0x04, 0x01, 0x01, 0x01, 0x0d, 0x01, 0x02, 0x01, 0x01, 0x01, 0x09, 0x00]);
+        value.extend(batch(&[]));
+        self.bytes.extend(klv(&key, &value));
+        self.partitions.push(offset);
+        self
+    }
+
+    // Primer and sets after the last partition pack
+    pub fn metadata(mut self, material_origin: Position, source_origin: Position) -> MxfFile {
+        let start = self.bytes.len();
+        let primer_key = [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x05, 0x01, 0x01, 0x0d, 0x01, 0x02, 0x01, 0x01, 0x05, 0x01, 0x00];
+        self.bytes.extend(klv(&primer_key, &primer()));
+        for (key, value) in sets(material_origin, source_origin) {
+            self.bytes.extend(klv(&key, &value));
+        }
+        let header_byte_count = (self.bytes.len() - start) as u64;
+        let partition = self.partitions.len() - 1;
+        self.set(partition, HEADER_BYTE_COUNT, header_byte_count)
+    }
+
+    pub fn essence(mut self, size: usize) -> MxfFile {
+        let key = [0x06, 0x0e, 0x2b, 0x34, 0x01, 0x02, 0x01, 0x01, 0x0d, 0x01, 0x03, 0x01, 0x15, 0x01, 0x05, 0x00];
+        self.bytes.extend(klv(&key, &vec![0; size]));
+        self
+    }
+
+    // Overwrite a field of a partition pack, past its key and 4-byte length
+    pub fn set(mut self, partition: usize, field: usize, value: u64) -> MxfFile {
+        let at = self.partitions[partition] as usize + 20 + field;
+        self.bytes[at..at + 8].copy_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    // FooterPartition of every pack, as a writer closing the file sets it
+    pub fn link_footer(mut self) -> MxfFile {
+        let footer = *self.partitions.last().unwrap();
+        for partition in 0..self.partitions.len() {
+            self = self.set(partition, FOOTER_PARTITION, footer);
+        }
+        self
+    }
+
+    // Random Index Pack listing every partition
+    pub fn rip(mut self) -> MxfFile {
+        let mut value = Vec::new();
+        for &offset in &self.partitions {
+            value.extend(1u32.to_be_bytes());
+            value.extend(offset.to_be_bytes());
+        }
+        value.extend(((16 + 4 + value.len() + 4) as u32).to_be_bytes());
+        self.bytes.extend(klv(&RIP_KEY, &value));
+        self
+    }
+
+    // Write the file to the temporary directory, returns its path
+    pub fn write(&self, name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("whereismyorigin-{}-{}", std::process::id(), name));
+        fs::write(&path, &self.bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+}
@@ -0,0 +1,153 @@
+//Edit rate conversions: edit units to seconds, samples and SMPTE timecode
+use std::fmt;
+
+// An edit rate such as 25/1, 30000/1001 or 48000/1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rational {
+    pub numerator: i32,
+    pub denominator: i32,
+}
+
+// Anything faster than this is an audio sample rate, not a frame rate
+const MAX_FRAME_RATE: f64 = 120.0;
+
+impl Rational {
+    // Rationals are stored as two 4-byte big endian signed integers
+    pub fn from_bytes(value: &[u8]) -> Option<Rational> {
+        if value.len() != 8 {
+            return None;
+        }
+        let numerator = i32::from_be_bytes([value[0], value[1], value[2], value[3]]);
+        let denominator = i32::from_be_bytes([value[4], value[5], value[6], value[7]]);
+        if numerator <= 0 || denominator <= 0 {
+            return None;
+        }
+        Some(Rational { numerator, denominator })
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    pub fn is_audio(&self) -> bool {
+        self.as_f64() > MAX_FRAME_RATE
+    }
+
+    // Length of a number of edit units in seconds
+    pub fn seconds(&self, edit_units: i64) -> f64 {
+        edit_units as f64 * self.denominator as f64 / self.numerator as f64
+    }
+
+    // Number of whole edit units of this rate in a number of edit units at another rate
+    pub fn convert(&self, edit_units: i64, from: &Rational) -> i64 {
+        let scaled = edit_units as i128 * self.numerator as i128 * from.denominator as i128;
+        let divisor = self.denominator as i128 * from.numerator as i128;
+        (scaled / divisor) as i64
+    }
+
+    // Timecode counts frames at the rounded rate, 29.97 counts as 30 with drop frame
+    fn timecode_base(&self) -> i64 {
+        (self.as_f64().round() as i64).max(1)
+    }
+
+    fn is_drop_frame(&self) -> bool {
+        self.denominator == 1001 && self.timecode_base() % 30 == 0
+    }
+
+    // SMPTE timecode of a frame count at this rate, HH:MM:SS:FF (HH:MM:SS;FF for drop frame)
+    pub fn timecode(&self, frames: i64) -> String {
+        let base = self.timecode_base() as u64;
+        let sign = if frames < 0 { "-" } else { "" };
+        // i64::MIN has no positive i64
+        let mut frames = frames.unsigned_abs();
+        let separator = if self.is_drop_frame() {
+            // Frame numbers 0 and 1 (0 to 3 at 60p) are skipped every minute except every tenth
+            let dropped = base / 15;
+            let per_minute = base * 60 - dropped;
+            let per_ten_minutes = per_minute * 10 + dropped;
+            let tens = frames / per_ten_minutes;
+            let rest = frames % per_ten_minutes;
+            frames += 9 * dropped * tens;
+            if rest > dropped {
+                frames += dropped * ((rest - dropped) / per_minute);
+            }
+            ';'
+        } else {
+            ':'
+        };
+        format!(
+            "{}{:02}:{:02}:{:02}{}{:02}",
+            sign,
+            frames / (base * 3600),
+            frames / (base * 60) % 60,
+            frames / base % 60,
+            separator,
+            frames % base
+        )
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+// Describe an Origin in the units editors use: edit units, seconds, frames and samples
+pub fn describe(origin: i64, edit_rate: &Rational, frame_rate: Option<&Rational>) -> String {
+    let seconds = edit_rate.seconds(origin);
+    if edit_rate.is_audio() {
+        let mut text = format!("{} samples @ {} = {:.6} s", origin, edit_rate, seconds);
+        if let Some(frame_rate) = frame_rate {
+            let frames = frame_rate.convert(origin, edit_rate);
+            text.push_str(&format!(" = {} @ {}", frame_rate.timecode(frames), frame_rate));
+        }
+        text
+    } else {
+        format!("{} edit units @ {} = {:.3} s = {}", origin, edit_rate, seconds, edit_rate.timecode(origin))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAL: Rational = Rational { numerator: 25, denominator: 1 };
+    const NTSC: Rational = Rational { numerator: 30000, denominator: 1001 };
+    const NTSC_60: Rational = Rational { numerator: 60000, denominator: 1001 };
+    const AUDIO: Rational = Rational { numerator: 48000, denominator: 1 };
+
+    #[test]
+    fn non_drop_frame_timecode() {
+        assert_eq!(PAL.timecode(0), "00:00:00:00");
+        assert_eq!(PAL.timecode(16), "00:00:00:16");
+        assert_eq!(PAL.timecode(25 * 3600 + 25 * 61 + 3), "01:01:01:03");
+        assert_eq!(PAL.timecode(-16), "-00:00:00:16");
+    }
+
+    #[test]
+    fn drop_frame_timecode_skips_frame_numbers() {
+        assert_eq!(NTSC.timecode(1799), "00:00:59;29");
+        assert_eq!(NTSC.timecode(1800), "00:01:00;02");
+        assert_eq!(NTSC.timecode(17981), "00:09:59;29");
+        assert_eq!(NTSC.timecode(17982), "00:10:00;00");
+        assert_eq!(NTSC.timecode(17982 + 1800), "00:11:00;02");
+        assert_eq!(NTSC_60.timecode(3600), "00:01:00;04");
+        assert_eq!(NTSC_60.timecode(35964), "00:10:00;00");
+    }
+
+    #[test]
+    fn most_negative_origin_doesnt_overflow() {
+        assert!(PAL.timecode(i64::MIN).starts_with('-'));
+        assert!(NTSC.timecode(i64::MIN).starts_with('-'));
+    }
+
+    #[test]
+    fn samples_are_converted_to_whole_frames() {
+        assert_eq!(PAL.convert(16, &AUDIO), 0);
+        assert_eq!(PAL.convert(1920, &AUDIO), 1);
+        assert_eq!(PAL.convert(48000, &AUDIO), 25);
+        assert_eq!(AUDIO.convert(1, &PAL), 1920);
+        assert_eq!(NTSC.convert(48048, &AUDIO), 30);
+    }
+}
@@ -0,0 +1,48 @@
+//Bounded pool of worker threads
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+// Run `work` on every item with at most `jobs` threads, and hand the results
+// to `done` on the calling thread in the order of the items
+pub fn run_ordered<T, R, W, D>(items: Vec<T>, jobs: usize, work: W, mut done: D)
+where
+    T: Sync,
+    R: Send,
+    W: Fn(&T) -> R + Sync,
+    D: FnMut(R),
+{
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1).min(items.len()) {
+            let tx = tx.clone();
+            let (next, items, work) = (&next, &items, &work);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+                    if tx.send((index, work(item))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        // Results arrive as workers finish, hold them back until their turn comes
+        let mut pending = BTreeMap::new();
+        let mut expected = 0;
+        for (index, result) in rx {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&expected) {
+                done(result);
+                expected += 1;
+            }
+        }
+    });
+}